@@ -0,0 +1,710 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use ChessAPI::board::*;
+use ChessAPI::piece::*;
+
+use crate::backend::ChessBackend;
+use crate::fen::Fen;
+use crate::rules;
+use crate::zobrist::Zobrist;
+
+pub const MAX_LEVEL: u32 = 5;
+
+const MATE: i32 = 100_000;
+const INFINITY: i32 = 1_000_000;
+const MAX_QUIESCENCE_DEPTH: u32 = 6;
+
+// Piece-square tables from White's point of view with rank 8 first, which
+// is the same order as the board's rows
+#[rustfmt::skip]
+const PAWN_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const KNIGHT_TABLE: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+
+#[rustfmt::skip]
+const BISHOP_TABLE: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const ROOK_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+     5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+     0,  0,  0,  5,  5,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const QUEEN_TABLE: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+     -5,  0,  5,  5,  5,  5,  0, -5,
+      0,  0,  5,  5,  5,  5,  0, -5,
+    -10,  5,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const KING_TABLE: [i32; 64] = [
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+     20, 20,  0,  0,  0,  0, 20, 20,
+     20, 30, 10,  0,  0, 10, 30, 20,
+];
+
+fn piece_value(piece_type: PieceType) -> i32 {
+    match piece_type {
+        PieceType::Pawn => 100,
+        PieceType::Knight => 320,
+        PieceType::Bishop => 330,
+        PieceType::Rook => 500,
+        PieceType::Queen => 900,
+        PieceType::King => 20_000,
+    }
+}
+
+// Material and piece-square score from the side to move's point of view
+fn evaluate(board: &dyn ChessBackend) -> i32 {
+    let mut score = 0;
+
+    for (row, rank) in board.get_board().iter().enumerate() {
+        for (col, square) in rank.iter().enumerate() {
+            let piece = match square {
+                Some(piece) => piece,
+                None => continue,
+            };
+
+            // Black's pieces read the tables upside down
+            let index = match piece.color {
+                Color::White => row * 8 + col,
+                Color::Black => (7 - row) * 8 + col,
+            };
+
+            let table = match piece.piece_type {
+                PieceType::Pawn => &PAWN_TABLE,
+                PieceType::Knight => &KNIGHT_TABLE,
+                PieceType::Bishop => &BISHOP_TABLE,
+                PieceType::Rook => &ROOK_TABLE,
+                PieceType::Queen => &QUEEN_TABLE,
+                PieceType::King => &KING_TABLE,
+            };
+
+            let value = piece_value(piece.piece_type) + table[index];
+            score += if piece.color == Color::White {
+                value
+            } else {
+                -value
+            };
+        }
+    }
+
+    match board.whose_turn() {
+        Color::White => score,
+        Color::Black => -score,
+    }
+}
+
+// Seeds must not be zero
+pub struct Random(pub u64);
+
+impl Random {
+    pub fn next(&mut self) -> u64 {
+        // xorshift64*
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545F4914F6CDD1D)
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+struct TableEntry {
+    depth: u32,
+    score: i32,
+    bound: Bound,
+    best: Option<(i8, i8, i8, i8)>,
+}
+
+fn move_key(mv: &Move) -> (i8, i8, i8, i8) {
+    (mv.from.col, mv.from.row, mv.to.col, mv.to.row)
+}
+
+// Mates are scored by their distance from the root, but a position can be
+// reached at any ply, so the table counts from the position itself
+fn score_to_table(score: i32, ply: i32) -> i32 {
+    if score >= MATE - 100 {
+        score + ply
+    } else if score <= 100 - MATE {
+        score - ply
+    } else {
+        score
+    }
+}
+
+fn score_from_table(score: i32, ply: i32) -> i32 {
+    if score >= MATE - 100 {
+        score - ply
+    } else if score <= 100 - MATE {
+        score + ply
+    } else {
+        score
+    }
+}
+
+struct Search {
+    zobrist: Zobrist,
+    table: HashMap<u64, TableEntry>,
+    deadline: Instant,
+    stop: Arc<AtomicBool>,
+    stopped: bool,
+    nodes: u64,
+    // Hashes of the positions that led to the one being searched, first from
+    // the game and then from the line being searched
+    path: Vec<u64>,
+}
+
+impl Search {
+    fn new(deadline: Instant, stop: Arc<AtomicBool>, history: Vec<u64>) -> Search {
+        Search {
+            zobrist: Zobrist::new(),
+            table: HashMap::new(),
+            deadline,
+            stop,
+            stopped: false,
+            nodes: 0,
+            path: history,
+        }
+    }
+
+    // A position that was already on the board is scored as a draw, since
+    // the side that is worse off can repeat it. Positions before the last
+    // capture or pawn move can not come back.
+    fn is_draw(&self, fen: &Fen, hash: u64) -> bool {
+        fen.halfmove >= 100
+            || self
+                .path
+                .iter()
+                .rev()
+                .take(fen.halfmove as usize)
+                .any(|&seen| seen == hash)
+    }
+
+    fn out_of_time(&mut self) -> bool {
+        // Checking the clock on every node is slow
+        if self.nodes % 1024 == 0
+            && (Instant::now() >= self.deadline || self.stop.load(Ordering::Relaxed))
+        {
+            self.stopped = true;
+        }
+        self.stopped
+    }
+
+    // Captures and promotions first, most valuable victim by least valuable
+    // attacker, with the move from the transposition table before everything
+    fn order_moves(
+        &self,
+        board: &dyn ChessBackend,
+        moves: &mut Vec<Move>,
+        best: Option<(i8, i8, i8, i8)>,
+    ) {
+        let squares = board.get_board();
+
+        moves.sort_by_cached_key(|mv| {
+            if Some(move_key(mv)) == best {
+                return -INFINITY;
+            }
+
+            let attacker = squares[mv.from.row as usize][mv.from.col as usize]
+                .map(|piece| piece_value(piece.piece_type))
+                .unwrap_or(0);
+
+            let mut score = 0;
+            if let Some(victim) = squares[mv.to.row as usize][mv.to.col as usize] {
+                score += 10 * piece_value(victim.piece_type) - attacker;
+            } else if rules::classify(board, mv).is_capture() {
+                score += 10 * piece_value(PieceType::Pawn) - attacker;
+            }
+            if let Some(promotion) = mv.promotion {
+                score += piece_value(promotion);
+            }
+
+            -score
+        });
+    }
+
+    fn quiescence(
+        &mut self,
+        board: &dyn ChessBackend,
+        mut alpha: i32,
+        beta: i32,
+        depth: u32,
+    ) -> i32 {
+        self.nodes += 1;
+
+        let stand_pat = evaluate(board);
+        if stand_pat >= beta || depth >= MAX_QUIESCENCE_DEPTH {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let mut moves = board
+            .generate_legal_moves()
+            .into_iter()
+            .filter(|mv| rules::classify(board, mv).is_capture())
+            .map(with_queen_promotion)
+            .collect::<Vec<Move>>();
+        self.order_moves(board, &mut moves, None);
+
+        for mv in moves {
+            if self.out_of_time() {
+                return alpha;
+            }
+
+            let mut child = board.box_clone();
+            if child.make_move(&mv).is_err() {
+                continue;
+            }
+
+            let score = -self.quiescence(&child, -beta, -alpha, depth + 1);
+            if score >= beta {
+                return score;
+            }
+            alpha = alpha.max(score);
+        }
+
+        alpha
+    }
+
+    // `fen` is the position on `board` with the castling rights and en
+    // passant square, which the board library does not keep
+    fn negamax(
+        &mut self,
+        board: &dyn ChessBackend,
+        fen: &Fen,
+        depth: u32,
+        ply: i32,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        self.nodes += 1;
+
+        let hash = self.zobrist.hash_fen(fen);
+        if ply > 0 && self.is_draw(fen, hash) {
+            return 0;
+        }
+
+        let mut best_from_table = None;
+
+        if let Some(entry) = self.table.get(&hash) {
+            best_from_table = entry.best;
+
+            if entry.depth >= depth && ply > 0 {
+                let score = score_from_table(entry.score, ply);
+                match entry.bound {
+                    Bound::Exact => return score,
+                    Bound::Lower if score >= beta => return score,
+                    Bound::Upper if score <= alpha => return score,
+                    _ => {}
+                }
+            }
+        }
+
+        let mut moves = expand_promotions(board, board.generate_legal_moves());
+        if moves.is_empty() {
+            return if board.is_check() { -MATE + ply } else { 0 };
+        }
+
+        if depth == 0 {
+            return self.quiescence(board, alpha, beta, 0);
+        }
+
+        self.order_moves(board, &mut moves, best_from_table);
+
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+
+        for mv in moves {
+            if self.out_of_time() {
+                break;
+            }
+
+            let mut child = board.box_clone();
+            if child.make_move(&mv).is_err() {
+                continue;
+            }
+            let child_fen = fen.after_move(&mv, child.get_board());
+
+            self.path.push(hash);
+            let score = -self.negamax(&child, &child_fen, depth - 1, ply + 1, -beta, -alpha);
+            self.path.pop();
+
+            if score > best_score {
+                best_score = score;
+                best_move = Some(move_key(&mv));
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        if !self.stopped {
+            let bound = if best_score <= original_alpha {
+                Bound::Upper
+            } else if best_score >= beta {
+                Bound::Lower
+            } else {
+                Bound::Exact
+            };
+
+            self.table.insert(
+                hash,
+                TableEntry {
+                    depth,
+                    score: score_to_table(best_score, ply),
+                    bound,
+                    best: best_move,
+                },
+            );
+        }
+
+        best_score
+    }
+}
+
+fn with_queen_promotion(mut mv: Move) -> Move {
+    if mv.promotion.is_none() && (mv.to.row == 0 || mv.to.row == 7) {
+        mv.promotion = Some(PieceType::Queen);
+    }
+    mv
+}
+
+// The board generates promotions without a piece, the search needs one move
+// per promotion piece
+pub fn expand_promotions(board: &dyn ChessBackend, moves: Vec<Move>) -> Vec<Move> {
+    let squares = board.get_board();
+    let mut expanded = Vec::with_capacity(moves.len());
+
+    for mv in moves {
+        let pawn = squares[mv.from.row as usize][mv.from.col as usize]
+            .map(|piece| piece.piece_type)
+            == Some(PieceType::Pawn);
+
+        if pawn && mv.promotion.is_none() && (mv.to.row == 0 || mv.to.row == 7) {
+            for piece_type in [
+                PieceType::Queen,
+                PieceType::Knight,
+                PieceType::Rook,
+                PieceType::Bishop,
+            ] {
+                let mut promotion = mv.clone();
+                promotion.promotion = Some(piece_type);
+                expanded.push(promotion);
+            }
+        } else {
+            expanded.push(mv);
+        }
+    }
+
+    expanded
+}
+
+// How deep and how long each difficulty level searches, and how many
+// centipawns of noise are added to the root moves once the search is done
+fn level_settings(level: u32) -> (u32, Duration, i32) {
+    match level {
+        0 | 1 => (1, Duration::from_millis(300), 150),
+        2 => (2, Duration::from_millis(700), 60),
+        3 => (3, Duration::from_millis(1500), 20),
+        4 => (4, Duration::from_millis(3000), 0),
+        _ => (64, Duration::from_millis(5000), 0),
+    }
+}
+
+pub struct SearchResult {
+    pub mv: Option<Move>,
+    // Centipawns from the side to move's point of view
+    pub score: i32,
+}
+
+// `fen` is the position on `board` and `history` holds the hashes of the
+// positions before it, see `Zobrist::hash_fen`
+pub fn search(
+    board: &dyn ChessBackend,
+    fen: &Fen,
+    history: Vec<u64>,
+    level: u32,
+    stop: Arc<AtomicBool>,
+) -> SearchResult {
+    let (max_depth, time, noise) = level_settings(level);
+    let mut search = Search::new(Instant::now() + time, stop, history);
+    search.path.push(search.zobrist.hash_fen(fen));
+
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(1);
+    let mut random = Random(seed | 1);
+
+    let mut root_moves = expand_promotions(board, board.generate_legal_moves());
+    let mut result = SearchResult {
+        mv: root_moves.first().cloned(),
+        score: 0,
+    };
+    // The root moves of the last iteration that was used, with their scores
+    let mut ranking = Vec::new();
+
+    for depth in 1..=max_depth {
+        let best = result.mv.as_ref().map(move_key);
+        search.order_moves(board, &mut root_moves, best);
+
+        let mut alpha = -INFINITY;
+        let mut iteration_best = None;
+        let mut scores = Vec::new();
+
+        for mv in &root_moves {
+            let mut child = board.box_clone();
+            if child.make_move(mv).is_err() {
+                continue;
+            }
+            let child_fen = fen.after_move(mv, child.get_board());
+
+            // Moves within twice the noise of the best one get an exact score,
+            // any of them may end up being played
+            let beta = -(alpha - 2 * noise);
+            let score = -search.negamax(&child, &child_fen, depth - 1, 1, -INFINITY, beta);
+            if search.stopped {
+                break;
+            }
+
+            scores.push((mv.clone(), score));
+            if score > alpha {
+                alpha = score;
+                iteration_best = Some(mv.clone());
+            }
+        }
+
+        // An unfinished iteration can not be trusted, unless it is the first
+        if search.stopped && depth > 1 {
+            break;
+        }
+
+        if let Some(mv) = iteration_best {
+            result = SearchResult {
+                mv: Some(mv),
+                score: alpha,
+            };
+            ranking = scores;
+        }
+
+        if search.stopped || alpha.abs() >= MATE - 100 {
+            break;
+        }
+    }
+
+    // The noise is only added to the final ranking, adding it during the
+    // search would throw off the alpha-beta bounds. Moves further behind only
+    // have an upper bound for a score.
+    if noise > 0 {
+        let best = result.score;
+        let noisy = ranking
+            .into_iter()
+            .filter(|(_, score)| *score > best - 2 * noise)
+            .map(|(mv, score)| {
+                let offset = (random.next() % (noise as u64 * 2 + 1)) as i32 - noise;
+                (mv, score, score + offset)
+            })
+            .max_by_key(|(_, _, noisy)| *noisy);
+
+        if let Some((mv, score, _)) = noisy {
+            result = SearchResult {
+                mv: Some(mv),
+                score,
+            };
+        }
+    }
+
+    result
+}
+
+// Searches on a background thread so the GUI keeps animating while the AI
+// thinks
+pub struct Ai {
+    pub level: u32,
+    receiver: Option<Receiver<SearchResult>>,
+    stop: Arc<AtomicBool>,
+    // From the last finished search, from the AI's point of view
+    pub score: Option<i32>,
+}
+
+impl Ai {
+    pub fn new(level: u32) -> Ai {
+        Ai {
+            level: level.min(MAX_LEVEL),
+            receiver: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            score: None,
+        }
+    }
+
+    pub fn thinking(&self) -> bool {
+        self.receiver.is_some()
+    }
+
+    pub fn start(&mut self, board: &dyn ChessBackend, fen: &Fen, history: &[u64]) {
+        self.cancel();
+
+        let (sender, receiver) = channel();
+        let board = board.box_clone();
+        let fen = fen.clone();
+        let history = history.to_vec();
+        let level = self.level;
+        let stop = Arc::new(AtomicBool::new(false));
+        self.stop = stop.clone();
+
+        thread::spawn(move || {
+            let _ = sender.send(search(&board, &fen, history, level, stop));
+        });
+
+        self.receiver = Some(receiver);
+    }
+
+    pub fn cancel(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.receiver = None;
+    }
+
+    // The chosen move once the search is done
+    pub fn poll(&mut self) -> Option<Move> {
+        let result = match &self.receiver {
+            Some(receiver) => match receiver.try_recv() {
+                Ok(result) => result,
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => {
+                    self.receiver = None;
+                    return None;
+                }
+            },
+            None => return None,
+        };
+
+        self.receiver = None;
+        self.score = Some(result.score);
+        result.mv
+    }
+}
+
+impl Drop for Ai {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::BackendKind;
+
+    const QUEEN_UP: &str = "4k3/8/8/8/8/8/8/3QK3 w - - 4 40";
+
+    fn queen_up() -> Fen {
+        Fen::parse(QUEEN_UP).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    // The score for White when the position is reached after `history`
+    fn score(history: Vec<u64>) -> i32 {
+        let fen = queen_up();
+        let board = BackendKind::ChessApi.board_from_fen(&fen).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(60);
+        let mut search = Search::new(deadline, Arc::new(AtomicBool::new(false)), history);
+        search.negamax(board.as_ref(), &fen, 2, 1, -INFINITY, INFINITY)
+    }
+
+    #[test]
+    fn repeated_positions_are_draws() {
+        let hash = Zobrist::new().hash_fen(&queen_up());
+
+        assert!(score(Vec::new()) > 500);
+        assert_eq!(score(vec![hash, 1, 2]), 0);
+
+        // Positions from before the last capture or pawn move can not come back
+        assert!(score(vec![hash, 1, 2, 3, 4]) > 500);
+    }
+
+    #[test]
+    fn castling_rights_change_the_hash() {
+        let zobrist = Zobrist::new();
+        let hash = |text: &str| {
+            zobrist.hash_fen(&Fen::parse(text).unwrap_or_else(|err| panic!("{}", err)))
+        };
+
+        assert_ne!(
+            hash("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1"),
+            hash("r3k2r/8/8/8/8/8/8/R3K2R w Kkq - 0 1")
+        );
+    }
+
+    #[test]
+    fn mates_keep_their_distance_in_the_table() {
+        // Mated at ply 5 is mated 2 plies after a position stored at ply 3,
+        // which is 6 plies from a root that reaches it at ply 4
+        let stored = score_to_table(-MATE + 5, 3);
+        assert_eq!(stored, -MATE + 2);
+        assert_eq!(score_from_table(stored, 4), -MATE + 6);
+
+        assert_eq!(score_from_table(score_to_table(MATE - 7, 2), 2), MATE - 7);
+        assert_eq!(score_to_table(250, 9), 250);
+    }
+}
@@ -0,0 +1,276 @@
+use ChessAPI::board::Move;
+use ChessAPI::piece::*;
+
+use crate::fen::Fen;
+
+// The move generator behind the GUI. Moves, pieces and colors are the types
+// of the ChessAPI library everywhere, other libraries convert to them.
+pub trait ChessBackend: Send {
+    // Indexed [row][col] with rank 8 as row 0
+    fn get_board(&self) -> [[Option<Piece>; 8]; 8];
+    fn whose_turn(&self) -> Color;
+    // Promotions may be generated without a piece, the piece is picked when
+    // the move is made
+    fn generate_legal_moves(&self) -> Vec<Move>;
+    fn make_move(&mut self, mv: &Move) -> Result<(), String>;
+    fn is_check(&self) -> bool;
+    fn is_checkmate(&self) -> bool;
+    fn is_stalemate(&self) -> bool;
+    fn fen(&self) -> String;
+    fn box_clone(&self) -> Box<dyn ChessBackend>;
+}
+
+pub type Board = Box<dyn ChessBackend>;
+
+// Lets a `&Board` be passed where a `&dyn ChessBackend` is expected
+impl<T: ChessBackend + ?Sized> ChessBackend for Box<T> {
+    fn get_board(&self) -> [[Option<Piece>; 8]; 8] {
+        (**self).get_board()
+    }
+
+    fn whose_turn(&self) -> Color {
+        (**self).whose_turn()
+    }
+
+    fn generate_legal_moves(&self) -> Vec<Move> {
+        (**self).generate_legal_moves()
+    }
+
+    fn make_move(&mut self, mv: &Move) -> Result<(), String> {
+        (**self).make_move(mv)
+    }
+
+    fn is_check(&self) -> bool {
+        (**self).is_check()
+    }
+
+    fn is_checkmate(&self) -> bool {
+        (**self).is_checkmate()
+    }
+
+    fn is_stalemate(&self) -> bool {
+        (**self).is_stalemate()
+    }
+
+    fn fen(&self) -> String {
+        (**self).fen()
+    }
+
+    fn box_clone(&self) -> Box<dyn ChessBackend> {
+        (**self).box_clone()
+    }
+}
+
+impl Clone for Box<dyn ChessBackend> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum BackendKind {
+    ChessApi,
+    Fritiofr,
+}
+
+impl BackendKind {
+    pub const ALL: [BackendKind; 2] = [BackendKind::ChessApi, BackendKind::Fritiofr];
+
+    pub fn parse(text: &str) -> Result<BackendKind, String> {
+        BackendKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == text)
+            .ok_or_else(|| format!("Unknown backend {}, expected chessapi or fritiofr", text))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BackendKind::ChessApi => "chessapi",
+            BackendKind::Fritiofr => "fritiofr",
+        }
+    }
+
+    pub fn board_from_fen(&self, fen: &Fen) -> Result<Board, String> {
+        Ok(match self {
+            BackendKind::ChessApi => Box::new(ChessApiBackend::from_fen(fen)?),
+            BackendKind::Fritiofr => Box::new(FritiofrBackend::from_fen(fen)?),
+        })
+    }
+
+    pub fn new_board(&self) -> Result<Board, String> {
+        self.board_from_fen(&Fen::start())
+            .map_err(|err| format!("The {} backend can not start a game: {}", self.name(), err))
+    }
+}
+
+// The board library only keeps track of the pieces and the side to move, the
+// rest of the FEN is tracked next to it
+#[derive(Clone)]
+pub struct ChessApiBackend {
+    board: ChessAPI::board::Board,
+    fen: Fen,
+}
+
+impl ChessApiBackend {
+    pub fn from_fen(fen: &Fen) -> Result<ChessApiBackend, String> {
+        let board = ChessAPI::board::Board::from_fen(&fen.to_string())
+            .map_err(|_| "the position is not playable".to_string())?;
+
+        Ok(ChessApiBackend {
+            board,
+            fen: fen.clone(),
+        })
+    }
+}
+
+impl ChessBackend for ChessApiBackend {
+    fn get_board(&self) -> [[Option<Piece>; 8]; 8] {
+        self.board.get_board()
+    }
+
+    fn whose_turn(&self) -> Color {
+        self.board.whose_turn()
+    }
+
+    fn generate_legal_moves(&self) -> Vec<Move> {
+        self.board.generate_legal_moves()
+    }
+
+    fn make_move(&mut self, mv: &Move) -> Result<(), String> {
+        self.board
+            .make_move(mv)
+            .map_err(|_| "illegal move".to_string())?;
+        self.fen = self.fen.after_move(mv, self.board.get_board());
+        Ok(())
+    }
+
+    fn is_check(&self) -> bool {
+        self.board.is_check()
+    }
+
+    fn is_checkmate(&self) -> bool {
+        self.board.is_checkmate()
+    }
+
+    fn is_stalemate(&self) -> bool {
+        self.board.is_stalemate()
+    }
+
+    fn fen(&self) -> String {
+        self.fen.to_string()
+    }
+
+    fn box_clone(&self) -> Box<dyn ChessBackend> {
+        Box::new(self.clone())
+    }
+}
+
+// fritiofr_chess numbers squares 0 to 63 from a8 to h1 like FEN does and
+// reads and writes full FEN strings, so the position is taken from its FEN
+// after every move instead of converting its piece types
+#[derive(Clone)]
+pub struct FritiofrBackend {
+    game: fritiofr_chess::Game,
+    fen: Fen,
+}
+
+impl FritiofrBackend {
+    pub fn from_fen(fen: &Fen) -> Result<FritiofrBackend, String> {
+        let game = fritiofr_chess::Game::from_fen(&fen.to_string())
+            .map_err(|_| "the position is not playable".to_string())?;
+
+        Ok(FritiofrBackend {
+            game,
+            fen: fen.clone(),
+        })
+    }
+
+    fn to_move(&self, mv: &fritiofr_chess::Move) -> Move {
+        let square = |index: usize| ChessAPI::board::Position {
+            col: (index % 8) as i8,
+            row: (index / 8) as i8,
+        };
+
+        Move {
+            from: square(mv.from),
+            to: square(mv.to),
+            promotion: None,
+        }
+    }
+}
+
+impl ChessBackend for FritiofrBackend {
+    fn get_board(&self) -> [[Option<Piece>; 8]; 8] {
+        self.fen.pieces
+    }
+
+    fn whose_turn(&self) -> Color {
+        self.fen.turn
+    }
+
+    fn generate_legal_moves(&self) -> Vec<Move> {
+        let mut moves = self
+            .game
+            .get_legal_moves()
+            .iter()
+            .map(|mv| self.to_move(mv))
+            .collect::<Vec<Move>>();
+
+        // One move per promotion piece comes back, not always next to each
+        // other, the GUI expects one per square
+        let squares = |mv: &Move| (mv.from.row, mv.from.col, mv.to.row, mv.to.col);
+        moves.sort_by_key(squares);
+        moves.dedup_by_key(|mv| squares(mv));
+        moves
+    }
+
+    fn make_move(&mut self, mv: &Move) -> Result<(), String> {
+        let index = |col: i8, row: i8| row as usize * 8 + col as usize;
+        let (from, to) = (index(mv.from.col, mv.from.row), index(mv.to.col, mv.to.row));
+
+        let promotion = match mv.promotion {
+            None => None,
+            Some(PieceType::Knight) => Some(fritiofr_chess::PieceType::Knight),
+            Some(PieceType::Bishop) => Some(fritiofr_chess::PieceType::Bishop),
+            Some(PieceType::Rook) => Some(fritiofr_chess::PieceType::Rook),
+            Some(PieceType::Queen) => Some(fritiofr_chess::PieceType::Queen),
+            Some(PieceType::Pawn | PieceType::King) => {
+                return Err("pawns can not promote to that piece".to_string())
+            }
+        };
+
+        let fritiofr_move = self
+            .game
+            .get_legal_moves()
+            .into_iter()
+            .find(|other| other.from == from && other.to == to && other.promotion == promotion)
+            .ok_or_else(|| "illegal move".to_string())?;
+
+        self.game
+            .make_move(&fritiofr_move)
+            .map_err(|_| "illegal move".to_string())?;
+
+        self.fen = Fen::parse(&self.game.to_fen()).map_err(|err| err.to_string())?;
+        Ok(())
+    }
+
+    fn is_check(&self) -> bool {
+        self.game.is_check()
+    }
+
+    fn is_checkmate(&self) -> bool {
+        self.game.is_checkmate()
+    }
+
+    fn is_stalemate(&self) -> bool {
+        self.game.is_stalemate()
+    }
+
+    fn fen(&self) -> String {
+        self.fen.to_string()
+    }
+
+    fn box_clone(&self) -> Box<dyn ChessBackend> {
+        Box::new(self.clone())
+    }
+}
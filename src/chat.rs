@@ -0,0 +1,140 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use piston::input::*;
+use piston::Event;
+
+use crate::layout;
+use crate::text_input::{InputEvent, TextInput};
+
+const MAX_MESSAGE_CHARS: usize = 200;
+
+pub struct ChatMessage {
+    // Hours and minutes when the message was written
+    pub time: String,
+    // None for notes, which are written by whoever sits at the board
+    pub author: Option<String>,
+    pub text: String,
+}
+
+impl ChatMessage {
+    pub fn line(&self) -> String {
+        match &self.author {
+            Some(author) => format!("{} {}: {}", self.time, author, self.text),
+            None => format!("{} {}", self.time, self.text),
+        }
+    }
+}
+
+// The chat with a network opponent, or the notes of a hot-seat game
+pub struct Chat {
+    pub messages: Vec<ChatMessage>,
+    pub input: TextInput,
+    // Typed messages have the keyboard instead of the shortcuts
+    pub focused: bool,
+    // Number of the newest messages scrolled out of view
+    pub scroll: usize,
+    // Messages written here that the network game has not sent yet
+    pub outgoing: Vec<String>,
+}
+
+impl Chat {
+    pub fn new() -> Chat {
+        Chat {
+            messages: Vec::new(),
+            input: TextInput::new(MAX_MESSAGE_CHARS),
+            focused: false,
+            scroll: 0,
+            outgoing: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, author: Option<String>, text: String) {
+        self.messages.push(ChatMessage {
+            time: time_of_day(),
+            author,
+            text,
+        });
+
+        // Stay on the same messages while reading back
+        if self.scroll > 0 {
+            self.scroll += 1;
+        }
+    }
+
+    // Clicking the input or pressing Return starts typing, which keeps the
+    // keyboard from the shortcuts until Escape or a click elsewhere. `area`
+    // is the whole chat and `field` its input. Returns None if the event was
+    // not used, sending and errors are left to the owner
+    pub fn event(
+        &mut self,
+        e: &Event,
+        area: [f64; 4],
+        field: [f64; 4],
+        cursor: [f64; 2],
+        ctrl_pressed: bool,
+        may_start: bool,
+    ) -> Option<InputEvent> {
+        if let Some([_, dy]) = e.mouse_scroll_args() {
+            if layout::contains(area, cursor) {
+                self.scroll_by(dy.signum() as isize);
+                return Some(InputEvent::Handled);
+            }
+        }
+
+        if let Some(Button::Mouse(MouseButton::Left)) = e.press_args() {
+            self.focused = layout::contains(field, cursor);
+            return self.focused.then_some(InputEvent::Handled);
+        }
+
+        if !self.focused {
+            let start = e.press_args() == Some(Button::Keyboard(Key::Return));
+            self.focused = start && may_start;
+            return self.focused.then_some(InputEvent::Handled);
+        }
+
+        match e.press_args() {
+            Some(Button::Keyboard(Key::PageUp)) => self.scroll_by(1),
+            Some(Button::Keyboard(Key::PageDown)) => self.scroll_by(-1),
+            _ => match self.input.event(e, ctrl_pressed) {
+                InputEvent::Ignored => return None,
+                InputEvent::Cancel => self.focused = false,
+                event => return Some(event),
+            },
+        }
+
+        Some(InputEvent::Handled)
+    }
+
+    // Takes the typed text, in network games it goes to the other side and
+    // otherwise it is kept as a note
+    pub fn send(&mut self, network: bool) {
+        let text = self.input.take().trim().to_string();
+        if text.is_empty() {
+            return;
+        }
+
+        self.scroll = 0;
+        match network {
+            true => {
+                self.push(Some("You".to_string()), text.clone());
+                self.outgoing.push(text);
+            }
+            false => self.push(None, text),
+        }
+    }
+
+    pub fn scroll_by(&mut self, messages: isize) {
+        let scroll = self.scroll as isize + messages;
+        self.scroll = scroll.clamp(0, self.messages.len().saturating_sub(1) as isize) as usize;
+    }
+}
+
+// There is no time zone information without another dependency, so the
+// times are in UTC
+fn time_of_day() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+
+    format!("{:02}:{:02}", seconds / 3600 % 24, seconds / 60 % 60)
+}
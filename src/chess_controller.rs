@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use piston::input::*;
 use piston::Event;
 
@@ -8,9 +10,81 @@ use crate::animation::AnimatePosition;
 use crate::animation::AnimateValue;
 use crate::animation::Animation;
 use crate::animation::AnimationTimingFunction;
+use crate::backend::Board;
+use crate::chat::Chat;
+use crate::clipboard;
+use crate::clock::color_index;
+use crate::clock::Clock;
+use crate::coords::BoardCoords;
+use crate::dialog::{ConfirmDialog, Dialog};
+use crate::fen;
+use crate::fen::Fen;
+use crate::game_picker::GamePicker;
+use crate::game_result::{GameResult, Termination};
+use crate::layout;
+use crate::layout::Layout;
+use crate::move_input;
+use crate::move_input::MoveInput;
+use crate::network::NetworkRole;
+use crate::options::Options;
+use crate::pgn;
+use crate::pgn::PgnGame;
+use crate::player::*;
+use crate::rules;
+use crate::rules::MoveKind;
+use crate::san;
+use crate::text_input::InputEvent;
+use crate::uci;
+use crate::zobrist::Zobrist;
+
+// The start of the status shown while a draw can be claimed
+const CLAIM_DRAW: &str = "Press C to claim a draw";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ResultAction {
+    Rematch,
+    Review,
+    Save,
+}
+
+impl ResultAction {
+    pub const ALL: [ResultAction; 3] = [
+        ResultAction::Rematch,
+        ResultAction::Review,
+        ResultAction::Save,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ResultAction::Rematch => "Rematch",
+            ResultAction::Review => "Review",
+            ResultAction::Save => "Save",
+        }
+    }
+}
+
+// The state of the game right before `mv` was played
+#[derive(Clone)]
+pub struct HistoryEntry {
+    pub board: Board,
+    pub fen: Fen,
+    pub mv: Move,
+    pub san: String,
+    pub last_from: Option<(usize, usize)>,
+    pub last_to: Option<(usize, usize)>,
+    pub check: Option<(usize, usize)>,
+    pub clock: Option<Clock>,
+    // Zobrist hash of `fen`
+    pub hash: u64,
+}
 
 pub struct ChessController {
+    pub options: Options,
+    // The name of the local player in network games, from --name or $USER
+    pub local_name: String,
     pub board: Board,
+    pub start_fen: Fen,
+    pub fen: Fen,
     pub from: Option<(usize, usize)>,
     pub last_from: Option<(usize, usize)>,
     pub last_to: Option<(usize, usize)>,
@@ -18,22 +92,66 @@ pub struct ChessController {
     pub check: Option<(usize, usize)>,
     pub cursor_pos: [f64; 2],
     pub animations: Vec<AnimatePosition>,
+    // The piece taken by the last move fades out on the square it stood on,
+    // which is not where the capturing piece lands for en passant
+    pub captured: Option<(Piece, (usize, usize))>,
+    pub captured_animation: AnimateValue,
+    pub dragging: bool,
+    pub flipped: bool,
+    pub auto_flip: bool,
     pub promotion_move: Option<Move>,
     pub promotion_dialog: bool,
     pub promotion_color: Color,
     pub promotion_animation: AnimateValue,
-    // 0 - Stalemate
-    // 1 - Black wins
-    // 2 - White wins
     pub end_state_show: bool,
-    pub end_state: Option<usize>,
+    pub game_result: Option<GameResult>,
+    // How a game that was taken back ended, it is over again once the moves
+    // are replayed up to the last one
+    browsed_result: Option<GameResult>,
     pub end_state_animation: AnimateValue,
+    pub clock: Option<Clock>,
+    // The clock of the game in play while an earlier position is shown
+    live_clock: Option<Clock>,
+    // A draw the player to move may claim with C
+    pub draw_claim: Option<Termination>,
+    pub dialog: ConfirmDialog,
+    // A draw offered by this side that is still waiting for an answer, which
+    // takes a while in network games
+    pub draw_offer: Option<Color>,
+    // Shown over the board while a network game waits for the other side,
+    // kept while the overlay fades out
+    pub waiting: Option<String>,
+    pub waiting_open: bool,
+    pub waiting_animation: AnimateValue,
+    zobrist: Zobrist,
+    pub history: Vec<HistoryEntry>,
+    pub redo_stack: Vec<HistoryEntry>,
+    // Indexed by `color_index`
+    pub players: [Box<dyn Player>; 2],
+    pub status: Option<String>,
+    // Talk in network games, notes in hot-seat games
+    pub chat: Chat,
+    pub move_input: MoveInput,
+    pub error: Option<String>,
+    pub error_animation: AnimateValue,
+    pub game_picker: GamePicker,
+    ctrl_pressed: bool,
 }
 
 impl ChessController {
-    pub fn new() -> ChessController {
-        ChessController {
-            board: Board::new(),
+    pub fn new(options: Options) -> Result<ChessController, String> {
+        let board = options.backend.new_board()?;
+        let local_name = options
+            .name
+            .clone()
+            .or_else(|| std::env::var("USER").ok())
+            .unwrap_or_else(|| "Player".to_string());
+        let mut controller = ChessController {
+            options,
+            local_name,
+            board,
+            start_fen: Fen::start(),
+            fen: Fen::start(),
             from: None,
             last_from: None,
             last_to: None,
@@ -41,6 +159,14 @@ impl ChessController {
             moves: Vec::new(),
             cursor_pos: [0.0, 0.0],
             animations: vec![],
+            captured: None,
+            captured_animation: AnimateValue::new()
+                .duration(0.2)
+                .timing_function(AnimationTimingFunction::Ease)
+                .finish(),
+            dragging: false,
+            flipped: false,
+            auto_flip: false,
 
             promotion_move: None,
             promotion_animation: AnimateValue::new()
@@ -51,15 +177,84 @@ impl ChessController {
             promotion_dialog: false,
 
             end_state_show: true,
-            end_state: None,
+            game_result: None,
+            browsed_result: None,
             end_state_animation: AnimateValue::new()
                 .duration(0.1)
                 .timing_function(AnimationTimingFunction::Ease)
                 .finish(),
+            clock: None,
+            live_clock: None,
+            draw_claim: None,
+            dialog: ConfirmDialog::new(),
+            draw_offer: None,
+            waiting: None,
+            waiting_open: false,
+            waiting_animation: AnimateValue::new()
+                .duration(0.1)
+                .timing_function(AnimationTimingFunction::Ease)
+                .finish(),
+            zobrist: Zobrist::new(),
+
+            history: Vec::new(),
+            redo_stack: Vec::new(),
+            players: [Box::new(HumanPlayer), Box::new(HumanPlayer)],
+            status: None,
+            chat: Chat::new(),
+            move_input: MoveInput::new(),
+            error: None,
+            error_animation: AnimateValue::new()
+                .duration(0.1)
+                .timing_function(AnimationTimingFunction::Ease)
+                .finish(),
+
+            game_picker: GamePicker::new(),
+
+            ctrl_pressed: false,
+        };
+
+        if let Some(fen) = controller.options.fen.clone() {
+            controller.set_position(&fen);
+        }
+
+        controller.clock = controller.options.time_control.clone().map(Clock::new);
+
+        for i in 0..2 {
+            match create_player(&controller.options.players[i]) {
+                Ok(player) => controller.players[i] = player,
+                Err(err) => controller.show_error(err),
+            }
         }
+
+        controller.flipped = controller.options.flipped;
+        controller.auto_flip = controller.options.auto_flip;
+        controller.auto_flip_board();
+
+        Ok(controller)
+    }
+
+    pub fn reset(&mut self) {
+        let controller = match ChessController::new(self.options.clone()) {
+            Ok(controller) => controller,
+            Err(err) => {
+                self.show_error(err);
+                return;
+            }
+        };
+
+        let (flipped, auto_flip) = (self.flipped, self.auto_flip);
+        let chat = std::mem::replace(&mut self.chat, Chat::new());
+
+        *self = controller;
+
+        self.chat = chat;
+        self.flipped = flipped;
+        self.auto_flip = auto_flip;
+        self.auto_flip_board();
     }
 
-    fn get_king_pos(&self, color: Color) -> (usize, usize) {
+    // Positions loaded from a FEN do not have to contain any kings
+    fn get_king_pos(&self, color: Color) -> Option<(usize, usize)> {
         (0..8)
             .map(|x| (0..8).map(move |y| (x, y)))
             .flatten()
@@ -70,65 +265,185 @@ impl ChessController {
                     return false;
                 }
             })
-            .unwrap()
     }
 
-    fn get_castle_move_rook_mv(
-        &self,
-        from: Position,
-        to: Position,
-    ) -> Option<((usize, usize), (usize, usize))> {
-        let piece = self.board.get_board()[from.row as usize][from.col as usize].unwrap();
-
-        if piece.piece_type != PieceType::King {
+    // Has to be called before the move is made on the board
+    fn get_castle_move_rook_mv(&self, mv: &Move) -> Option<((usize, usize), (usize, usize))> {
+        if rules::classify(&self.board, mv) != MoveKind::Castle {
             return None;
         }
 
-        if from.col == 4 && to.col == 6 {
-            return Some(((7, from.row as usize), (5, from.row as usize)));
+        let row = mv.from.row as usize;
+        match mv.to.col {
+            6 => Some(((7, row), (5, row))),
+            _ => Some(((0, row), (3, row))),
         }
+    }
 
-        if from.col == 4 && to.col == 2 {
-            return Some(((0, from.row as usize), (3, from.row as usize)));
+    // `size` is the size of the window
+    pub fn event(&mut self, size: [f64; 2], e: &Event) {
+        match (e.press_args(), e.release_args()) {
+            (Some(Button::Keyboard(Key::LCtrl | Key::RCtrl)), _) => self.ctrl_pressed = true,
+            (_, Some(Button::Keyboard(Key::LCtrl | Key::RCtrl))) => self.ctrl_pressed = false,
+            _ => {}
         }
 
-        None
-    }
+        if let Some(pos) = e.mouse_cursor_args() {
+            self.cursor_pos = pos;
+        }
+
+        if self.error.is_none() && !self.dialog.open && self.typing_event(size, e) {
+            return;
+        }
 
-    pub fn event(&mut self, size: [u32; 2], e: &Event) {
         if let Some(Button::Keyboard(key)) = e.press_args() {
             match key {
-                Key::R => {
-                    *self = ChessController::new();
+                Key::R if self.ctrl_pressed => {
+                    self.request_resign();
+                    return;
+                }
+                Key::D if self.ctrl_pressed => {
+                    self.request_draw();
+                    return;
+                }
+                Key::N if self.ctrl_pressed && !self.is_network_game() => {
+                    self.open_dialog(Dialog::NewGame);
+                    return;
+                }
+                Key::S if self.ctrl_pressed => {
+                    self.save_pgn();
+                    return;
+                }
+                Key::O if self.ctrl_pressed && !self.is_network_game() => {
+                    self.load_pgn_file();
+                    return;
+                }
+                Key::F => {
+                    self.flipped = !self.flipped;
+                    return;
+                }
+                Key::A if !self.ctrl_pressed => {
+                    self.auto_flip = !self.auto_flip;
+                    self.auto_flip_board();
+                    return;
+                }
+                Key::C if self.ctrl_pressed => {
+                    self.copy_fen();
+                    return;
+                }
+                Key::V if self.ctrl_pressed && !self.is_network_game() => {
+                    self.paste_fen();
+                    return;
+                }
+                Key::C if self.draw_claim.is_some() && self.human_to_move() => {
+                    self.claim_draw();
                     return;
                 }
                 _ => {}
             }
         }
 
-        if let Some(pos) = e.mouse_cursor_args() {
-            self.cursor_pos = pos;
+        if self.error.is_some() {
+            if let Some(Button::Keyboard(_) | Button::Mouse(_)) = e.press_args() {
+                self.error = None;
+            }
+            return;
+        }
+
+        if self.dialog.open {
+            let buttons = self.layout(size).confirm_buttons();
+            if let Some(yes) = self.dialog.answer(e, buttons, self.cursor_pos) {
+                self.answer_dialog(yes);
+            }
+            return;
+        }
+
+        if self.waiting_open {
+            return;
+        }
+
+        if self.game_picker.open {
+            if let Some(Button::Keyboard(key)) = e.press_args() {
+                if let Some(index) = self.game_picker.key(key) {
+                    self.load_game(index);
+                }
+            }
+            return;
         }
 
         if !self.animations.is_empty() {
             return;
         }
 
+        if let Some(Button::Keyboard(key)) = e.press_args() {
+            match key {
+                Key::Z if self.ctrl_pressed => {
+                    self.take_back();
+                    return;
+                }
+                Key::Y if self.ctrl_pressed => {
+                    self.replay_move();
+                    return;
+                }
+                Key::Left => {
+                    self.take_back();
+                    return;
+                }
+                Key::Right => {
+                    self.replay_move();
+                    return;
+                }
+                Key::Home => {
+                    self.go_to_start();
+                    return;
+                }
+                Key::End => {
+                    self.go_to_end();
+                    return;
+                }
+                Key::G if self.game_picker.games.len() > 1 => {
+                    self.game_picker.open();
+                    return;
+                }
+                _ => {}
+            }
+        }
+
         if let Some(Button::Mouse(MouseButton::Left)) = e.press_args() {
-            if self.end_state.is_some() {
+            if self.game_result.is_some() {
+                if self.end_state_show {
+                    let layout = self.layout(size);
+                    let clicked = ResultAction::ALL
+                        .iter()
+                        .zip(layout.result_buttons())
+                        .find(|(_, button)| layout::contains(*button, self.cursor_pos));
+
+                    if let Some((action, _)) = clicked {
+                        self.result_action(*action);
+                        return;
+                    }
+                }
+
                 self.end_state_show = !self.end_state_show;
                 self.end_state_animation.reset();
                 return;
             }
 
+            let buttons = self.layout(size).action_buttons();
+            if layout::contains(buttons[0], self.cursor_pos) {
+                self.request_resign();
+                return;
+            } else if layout::contains(buttons[1], self.cursor_pos) {
+                self.request_draw();
+                return;
+            }
+
             if self.promotion_dialog {
-                let width = 600.0;
-                let size = width / 8.0;
-                let gap = 10.0;
+                let coords = self.coords(size);
+                let size = coords.square_size();
 
                 for i in 0..4 {
-                    let x = width / 2.0 - (size + gap) * 2.0 + gap / 2.0 + (size + gap) * i as f64;
-                    let y = width / 2.0 - size / 2.0;
+                    let [x, y] = coords.promotion_option(i);
 
                     let [m_x, m_y] = self.cursor_pos;
 
@@ -159,111 +474,1353 @@ impl ChessController {
                 return;
             }
 
-            let x = (self.cursor_pos[0]) / ((size[0] as f64) / 8.0);
-            let y = (self.cursor_pos[1]) / ((size[1] as f64) / 8.0);
-
-            let x = x as usize;
-            let y = y as usize;
-
-            let mv = self
-                .moves
-                .iter()
-                .find(|mv| (mv.to.col, mv.to.row) == (x as i8, y as i8));
-
-            if let Some(mv) = mv {
-                if self.board.get_board()[mv.from.row as usize][mv.from.col as usize]
-                    .unwrap()
-                    .piece_type
-                    == PieceType::Pawn
-                    && (mv.to.row == 0 || mv.to.row == 7)
-                {
-                    self.promotion_color = self.board.whose_turn();
-                    self.promotion_move = Some(mv.clone());
-                    self.promotion_dialog = true;
-                    self.promotion_animation.reset();
-                    return;
-                }
+            // The board belongs to the side to move unless that is a human here
+            if !self.human_to_move() {
+                return;
+            }
+
+            // Moving while browsing would take back moves the other side
+            // already knows about
+            if self.is_network_game() && !self.redo_stack.is_empty() {
+                return;
+            }
+
+            let (x, y) = match self.coords(size).square_at(self.cursor_pos) {
+                Some(square) => square,
+                None => return,
+            };
+
+            if self.try_move_to((x, y)) {
+                return;
+            }
+
+            let moves = self
+                .board
+                .generate_legal_moves()
+                .into_iter()
+                .filter(|mv| (mv.from.col, mv.from.row) == (x as i8, y as i8))
+                .collect::<Vec<Move>>();
 
-                self.make_move(&mv.clone());
+            if moves.len() > 0 {
+                self.from = Some((x, y));
+                self.moves = moves;
+                self.dragging = true;
             } else {
-                let moves = self
-                    .board
-                    .generate_legal_moves()
-                    .into_iter()
-                    .filter(|mv| (mv.from.col, mv.from.row) == (x as i8, y as i8))
-                    .collect::<Vec<Move>>();
-
-                if moves.len() > 0 {
-                    self.from = Some((x as usize, y as usize));
-                    self.moves = moves;
-                } else {
-                    self.from = None;
-                    self.moves = Vec::new();
-                }
+                self.from = None;
+                self.moves = Vec::new();
+            }
+        }
+
+        if let Some(Button::Mouse(MouseButton::Left)) = e.release_args() {
+            if self.dragging {
+                self.drop_piece(size);
             }
         }
     }
 
-    fn make_move(&mut self, mv: &Move) {
-        self.animations = {
-            let mut mvs = vec![AnimatePosition::new()
-                .duration(0.2)
-                .timing_function(AnimationTimingFunction::Ease)
-                .start((mv.from.col as f64, mv.from.row as f64))
-                .end((mv.to.col as f64, mv.to.row as f64))];
+    pub fn layout(&self, size: [f64; 2]) -> Layout {
+        Layout::new(
+            size,
+            self.clock.is_some(),
+            self.chat_shown(),
+            self.move_input_shown(),
+        )
+    }
 
-            if let Some((from, to)) = self.get_castle_move_rook_mv(mv.from, mv.to) {
-                mvs.push(
-                    AnimatePosition::new()
-                        .duration(0.2)
-                        .timing_function(AnimationTimingFunction::Ease)
-                        .start((from.0 as f64, from.1 as f64))
-                        .end((to.0 as f64, to.1 as f64)),
-                );
+    // Spectators and games without a human here have nothing to type
+    pub fn move_input_shown(&self) -> bool {
+        match &self.options.network {
+            Some(role) => !matches!(role, NetworkRole::Watch(_)),
+            None => self.players.iter().any(|player| player.is_human()),
+        }
+    }
+
+    // The move input and the chat get events before the board, only one of
+    // them has the keyboard at a time. Returns whether the event was used
+    fn typing_event(&mut self, size: [f64; 2], e: &Event) -> bool {
+        let layout = self.layout(size);
+        let may_start = !self.waiting_open && !self.game_picker.open;
+
+        if self.move_input_shown() {
+            let event = self.move_input.event(
+                e,
+                layout.move_field(),
+                self.cursor_pos,
+                self.ctrl_pressed,
+                may_start && !self.chat.focused,
+            );
+
+            if self.move_input.focused {
+                self.chat.focused = false;
+            }
+
+            let used = event.is_some();
+            match event {
+                Some(InputEvent::Submit) => self.play_typed_move(),
+                Some(InputEvent::Error(err)) => self.show_error(err),
+                _ => {}
+            }
+
+            if used {
+                return true;
+            }
+        }
+
+        if !self.chat_shown() {
+            return false;
+        }
+
+        let event = self.chat.event(
+            e,
+            layout.chat,
+            layout.chat_input(),
+            self.cursor_pos,
+            self.ctrl_pressed,
+            may_start,
+        );
+
+        let network = self.is_network_game();
+        match event {
+            Some(InputEvent::Submit) => self.chat.send(network),
+            Some(InputEvent::Error(err)) => self.show_error(err),
+            Some(_) => {}
+            None => return false,
+        }
+
+        true
+    }
+
+    // Refused whenever a click on the board would be
+    fn play_typed_move(&mut self) {
+        let error = if self.waiting_open {
+            Some("Wait for the other side to connect".to_string())
+        } else if self.game_picker.open {
+            Some("Pick a game first".to_string())
+        } else if self.promotion_dialog {
+            Some("Pick the piece to promote to first".to_string())
+        } else if !self.animations.is_empty() {
+            Some("Wait for the last move to finish".to_string())
+        } else if self.game_result.is_some() {
+            Some("The game is over".to_string())
+        } else if !self.human_to_move() {
+            Some("It is not your turn".to_string())
+        } else if self.is_network_game() && !self.redo_stack.is_empty() {
+            Some("Go to the last move to play".to_string())
+        } else {
+            None
+        };
+
+        let mv = match error {
+            Some(err) => Err(err),
+            None => move_input::parse_move(&self.board, &self.move_input.input.text),
+        };
+
+        match mv {
+            Ok(mv) => {
+                self.move_input.input.clear();
+                self.move_input.error = None;
+                self.make_move(&mv);
             }
+            Err(err) => self.move_input.error = Some(err),
+        }
+    }
+
+    // Players of a network game talk to each other and hot-seat players can
+    // keep notes, spectators only watch
+    pub fn chat_shown(&self) -> bool {
+        match &self.options.network {
+            Some(role) => !matches!(role, NetworkRole::Watch(_)),
+            None => self.players.iter().all(|player| player.is_human()),
+        }
+    }
+
+    pub fn coords(&self, size: [f64; 2]) -> BoardCoords {
+        self.layout(size).board_coords(self.flipped)
+    }
+
+    fn auto_flip_board(&mut self) {
+        if self.auto_flip {
+            self.flipped = self.board.whose_turn() == Color::Black;
+        }
+    }
+
+    // Board coordinates of the top left corner of a piece held by the cursor
+    fn held_piece_pos(&self, size: [f64; 2]) -> (f64, f64) {
+        let coords = self.coords(size);
+        let half = coords.square_size() / 2.0;
+
+        coords.to_board([self.cursor_pos[0] - half, self.cursor_pos[1] - half])
+    }
+
+    // Plays the selected piece's move to `square`, opening the promotion
+    // dialog if needed. Returns false if there is no such move.
+    fn try_move_to(&mut self, (x, y): (usize, usize)) -> bool {
+        let mv = match self
+            .moves
+            .iter()
+            .find(|mv| (mv.to.col, mv.to.row) == (x as i8, y as i8))
+        {
+            Some(mv) => mv.clone(),
+            None => return false,
+        };
+
+        if self.board.get_board()[mv.from.row as usize][mv.from.col as usize]
+            .unwrap()
+            .piece_type
+            == PieceType::Pawn
+            && (mv.to.row == 0 || mv.to.row == 7)
+        {
+            self.promotion_color = self.board.whose_turn();
+            self.promotion_move = Some(mv);
+            self.promotion_dialog = true;
+            self.promotion_animation.reset();
+            return true;
+        }
+
+        self.make_move(&mv);
+        true
+    }
 
-            mvs
+    fn drop_piece(&mut self, size: [f64; 2]) {
+        self.dragging = false;
+
+        let from = match self.from {
+            Some(from) => from,
+            None => return,
         };
 
-        self.board.make_move(mv).unwrap();
+        let square = self.coords(size).square_at(self.cursor_pos);
+
+        // Releasing on the same square is a normal click, the piece stays selected
+        if square == Some(from) {
+            return;
+        }
+
+        let drop_pos = self.held_piece_pos(size);
+
+        if let Some(square) = square {
+            if self.try_move_to(square) {
+                // The piece is already where it was dropped, only the last bit
+                // into the center of the square is animated
+                if let Some(animation) = self.animations.first_mut() {
+                    let end = animation.end;
+                    *animation = AnimatePosition::new()
+                        .duration(0.1)
+                        .timing_function(AnimationTimingFunction::Ease)
+                        .start(drop_pos)
+                        .end(end);
+                }
+                return;
+            }
+        }
+
+        self.animations = vec![AnimatePosition::new()
+            .duration(0.2)
+            .timing_function(AnimationTimingFunction::Ease)
+            .start(drop_pos)
+            .end((from.0 as f64, from.1 as f64))];
+    }
+
+    fn animate_move(from: (usize, usize), to: (usize, usize)) -> AnimatePosition {
+        AnimatePosition::new()
+            .duration(0.2)
+            .timing_function(AnimationTimingFunction::Ease)
+            .start((from.0 as f64, from.1 as f64))
+            .end((to.0 as f64, to.1 as f64))
+    }
+
+    // Has to be called before the move is made on the board
+    fn move_animations(&self, mv: &Move) -> Vec<AnimatePosition> {
+        let mut mvs = vec![Self::animate_move(
+            (mv.from.col as usize, mv.from.row as usize),
+            (mv.to.col as usize, mv.to.row as usize),
+        )];
+
+        if let Some((from, to)) = self.get_castle_move_rook_mv(mv) {
+            mvs.push(Self::animate_move(from, to));
+        }
+
+        mvs
+    }
+
+    fn close_promotion_dialog(&mut self) {
+        if self.promotion_dialog {
+            self.promotion_animation.reset();
+        }
+
+        self.promotion_move = None;
+        self.promotion_dialog = false;
+    }
+
+    fn make_move(&mut self, mv: &Move) {
+        let mover = self.board.whose_turn();
+        if let Err(err) = self.apply_move(mv) {
+            self.show_error(err);
+            return;
+        }
+
+        // Moving instead of answering declines a draw offer
+        if self.draw_offer.map_or(false, |color| color != mover) {
+            self.draw_offer = None;
+        }
+
+        // Playing on from an earlier position goes on with its clock
+        self.redo_stack.clear();
+        self.live_clock = None;
+        self.browsed_result = None;
+    }
+
+    // Leaves the game as it was if the board does not take the move
+    fn apply_move(&mut self, mv: &Move) -> Result<(), String> {
+        let mut board = self.board.clone();
+        board.make_move(mv).map_err(|err| {
+            format!(
+                "{}{} can not be played: {}",
+                san::square_name(mv.from),
+                san::square_name(mv.to),
+                err
+            )
+        })?;
+
+        self.history.push(HistoryEntry {
+            board: self.board.clone(),
+            fen: self.fen.clone(),
+            mv: mv.clone(),
+            san: san::move_to_san(&self.board, mv),
+            last_from: self.last_from,
+            last_to: self.last_to,
+            check: self.check,
+            clock: self.clock.clone(),
+            hash: self.zobrist.hash_fen(&self.fen),
+        });
+
+        self.animations = self.move_animations(mv);
+        self.captured = rules::captured_square(&self.board, mv).and_then(|(col, row)| {
+            self.board.get_board()[row][col].map(|piece| (piece, (col, row)))
+        });
+        self.captured_animation.reset();
+
+        let mover = self.board.whose_turn();
+        self.board = board;
+        self.fen = self.fen.after_move(mv, self.board.get_board());
+
+        if let Some(clock) = &mut self.clock {
+            clock.move_made(mover);
+        }
 
         self.last_from = Some((mv.from.col as usize, mv.from.row as usize));
         self.last_to = Some((mv.to.col as usize, mv.to.row as usize));
 
+        self.from = None;
+        self.moves = Vec::new();
+
+        self.update_game_state();
+        Ok(())
+    }
+
+    fn update_game_state(&mut self) {
+        self.auto_flip_board();
+
         if self.board.is_check() || self.board.is_checkmate() {
             let current_turn = self.board.whose_turn();
-            self.check = Some(self.get_king_pos(current_turn));
+            self.check = self.get_king_pos(current_turn);
         } else {
             self.check = None;
         }
 
-        self.from = None;
-        self.moves = Vec::new();
+        // Network games can not be taken back, their result stays while the
+        // moves are looked through
+        if self.game_result.is_some() {
+            self.update_draw_claim();
+            return;
+        }
 
         if self.board.is_checkmate() {
-            self.end_state = Some(if self.board.whose_turn() == Color::White {
-                1
-            } else {
-                2
-            });
-            self.end_state_animation.reset();
+            let winner = match self.board.whose_turn() {
+                Color::White => Color::Black,
+                Color::Black => Color::White,
+            };
+            self.end_game(GameResult::win(winner, Termination::Checkmate));
         } else if self.board.is_stalemate() {
-            self.end_state = Some(0);
-            self.end_state_animation.reset();
+            self.end_game(GameResult::draw(Termination::Stalemate));
+        } else if !rules::can_checkmate(&self.fen.pieces, Color::White)
+            && !rules::can_checkmate(&self.fen.pieces, Color::Black)
+        {
+            self.end_game(GameResult::draw(Termination::InsufficientMaterial));
+        } else if self.repetitions() >= 5 {
+            self.end_game(GameResult::draw(Termination::FivefoldRepetition));
+        } else if self.fen.halfmove >= 150 {
+            self.end_game(GameResult::draw(Termination::SeventyFiveMoves));
         }
+
+        self.update_draw_claim();
     }
 
-    pub fn update(&mut self, args: &UpdateArgs) {
-        if self.end_state.is_some() {
-            self.end_state_animation.tick_dt(args.dt);
+    // How many times the current position has occurred, counting this time
+    pub fn repetitions(&self) -> usize {
+        let hash = self.zobrist.hash_fen(&self.fen);
+
+        // Positions before the last capture or pawn move can not come back
+        let reversible = self.fen.halfmove as usize;
+        1 + self
+            .history
+            .iter()
+            .rev()
+            .take(reversible)
+            .filter(|entry| entry.hash == hash)
+            .count()
+    }
+
+    fn update_draw_claim(&mut self) {
+        self.draw_claim = if self.game_result.is_some() {
+            None
+        } else if self.repetitions() >= 3 {
+            Some(Termination::Repetition)
+        } else if self.fen.halfmove >= 100 {
+            Some(Termination::FiftyMoves)
+        } else {
+            None
+        };
+
+        match self.draw_claim {
+            Some(termination) if self.human_to_move() => {
+                let description = GameResult::draw(termination).description();
+                self.status = Some(format!("{} {}", CLAIM_DRAW, description));
+            }
+            // The prompt goes away once the draw can not be claimed anymore
+            _ => {
+                let prompt = self.status.as_deref().map_or(false, |s| s.starts_with(CLAIM_DRAW));
+                if prompt {
+                    self.status = None;
+                }
+            }
+        }
+    }
+
+    pub fn claim_draw(&mut self) {
+        if let Some(termination) = self.draw_claim.take() {
+            self.end_game(GameResult::draw(termination));
+            self.update_draw_claim();
         }
+    }
 
-        self.promotion_animation.tick_dt(args.dt);
+    pub fn end_game(&mut self, result: GameResult) {
+        self.game_result = Some(result);
+        self.end_state_show = true;
+        self.end_state_animation.reset();
+    }
 
-        for a in self.animations.iter_mut() {
-            a.tick_dt(args.dt);
+    // The side a resignation or draw offer comes from: the human to move, or
+    // the only human when playing against the computer
+    pub fn local_human(&self) -> Option<Color> {
+        let turn = self.board.whose_turn();
+        if self.human_to_move() {
+            return Some(turn);
         }
 
-        self.animations.retain(|a| !a.is_done());
+        [Color::White, Color::Black]
+            .into_iter()
+            .find(|color| self.players[color_index(*color)].is_human())
+    }
+
+    // Whether the resign and draw buttons do anything
+    pub fn can_resign_or_offer(&self) -> bool {
+        self.game_result.is_none()
+            && self.redo_stack.is_empty()
+            && !self.waiting_open
+            && self.local_human().is_some()
+    }
+
+    fn open_dialog(&mut self, dialog: Dialog) {
+        self.close_promotion_dialog();
+        self.dragging = false;
+
+        self.dialog.open(dialog);
+    }
+
+    pub fn request_resign(&mut self) {
+        if let (true, Some(color)) = (self.can_resign_or_offer(), self.local_human()) {
+            self.open_dialog(Dialog::Resign(color));
+        }
+    }
+
+    pub fn request_draw(&mut self) {
+        if self.draw_offer.is_some() {
+            return;
+        }
+
+        if let (true, Some(color)) = (self.can_resign_or_offer(), self.local_human()) {
+            self.open_dialog(Dialog::OfferDraw(color));
+        }
+    }
+
+    fn answer_dialog(&mut self, yes: bool) {
+        let dialog = match self.dialog.close() {
+            Some(dialog) => dialog,
+            None => return,
+        };
+
+        match (dialog, yes) {
+            (Dialog::Resign(color), true) => self.resign(color),
+            (Dialog::OfferDraw(color), true) => self.offer_draw(color),
+            (Dialog::DrawOffered(_), true) => {
+                self.draw_offer = None;
+                self.end_game(GameResult::draw(Termination::Agreement));
+            }
+            (Dialog::DrawOffered(color), false) => {
+                self.draw_offer = None;
+                let opponent = fen::color_name(fen::opposite(color));
+                self.status = Some(format!("{} declines the draw", opponent));
+            }
+            (Dialog::NewGame, true) => self.reset(),
+            (_, false) => {}
+        }
+    }
+
+    pub fn resign(&mut self, color: Color) {
+        self.end_game(GameResult::win(
+            fen::opposite(color),
+            Termination::Resignation,
+        ));
+    }
+
+    // Asks the opponent of `color`, computer players answer right away
+    pub fn offer_draw(&mut self, color: Color) {
+        let opponent = fen::opposite(color);
+        let player = &mut self.players[color_index(opponent)];
+        let name = player.name();
+        let human = player.is_human();
+
+        match player.accepts_draw() {
+            Some(true) => {
+                self.status = Some(format!("{} accepts the draw", name));
+                self.end_game(GameResult::draw(Termination::Agreement));
+            }
+            Some(false) => self.status = Some(format!("{} declines the draw", name)),
+            None if human => self.open_dialog(Dialog::DrawOffered(color)),
+            None => {
+                self.draw_offer = Some(color);
+                self.status = Some(format!("Waiting for {} to answer the draw offer", name));
+            }
+        }
+    }
+
+    // An offer from the other side of a network game, answered in the dialog
+    pub fn receive_draw_offer(&mut self, color: Color) {
+        self.draw_offer = Some(color);
+        self.open_dialog(Dialog::DrawOffered(color));
+    }
+
+    pub fn show_waiting(&mut self, message: String) {
+        if !self.waiting_open {
+            self.waiting_open = true;
+            self.waiting_animation.reset();
+        }
+        self.waiting = Some(message);
+    }
+
+    pub fn hide_waiting(&mut self) {
+        if self.waiting_open {
+            self.waiting_open = false;
+            self.waiting_animation.reset();
+        }
+    }
+
+    pub fn is_network_game(&self) -> bool {
+        self.options.network.is_some()
+    }
+
+    // Only the host's clock ends a network game, so that both sides agree on
+    // who ran out of time
+    fn decides_timeouts(&self) -> bool {
+        !matches!(
+            self.options.network,
+            Some(NetworkRole::Join(_) | NetworkRole::Watch(_))
+        )
+    }
+
+    pub fn result_action(&mut self, action: ResultAction) {
+        match action {
+            ResultAction::Rematch if self.is_network_game() => {
+                self.status = Some("Start a new network game to play again".to_string());
+            }
+            ResultAction::Rematch => self.rematch(),
+            ResultAction::Review => {
+                self.end_state_show = false;
+                self.end_state_animation.reset();
+                self.go_to_start();
+            }
+            ResultAction::Save => self.save_pgn(),
+        }
+    }
+
+    // A new game with the sides swapped
+    pub fn rematch(&mut self) {
+        self.options.players.swap(0, 1);
+
+        // Keep a single human at the bottom of the board
+        if self
+            .players
+            .iter()
+            .filter(|player| player.is_human())
+            .count()
+            == 1
+        {
+            self.options.flipped = !self.options.flipped;
+            self.flipped = !self.flipped;
+        }
+
+        self.reset();
+    }
+
+    // The side to move ran out of time, they lose unless the opponent could
+    // never checkmate them
+    fn flag(&mut self) {
+        let turn = self.board.whose_turn();
+        let opponent = match turn {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        };
+
+        self.close_promotion_dialog();
+        self.dragging = false;
+        self.from = None;
+        self.moves = Vec::new();
+
+        self.end_game(if rules::can_checkmate(&self.fen.pieces, opponent) {
+            GameResult::win(opponent, Termination::Timeout)
+        } else {
+            GameResult::draw(Termination::Timeout)
+        });
+    }
+
+    // Every move in the game in SAN, including the ones that can be redone
+    pub fn san_moves(&self) -> Vec<&str> {
+        self.history
+            .iter()
+            .chain(self.redo_stack.iter().rev())
+            .map(|entry| entry.san.as_str())
+            .collect()
+    }
+
+    pub fn result(&self) -> &'static str {
+        self.game_result
+            .or(self.browsed_result)
+            .map_or("*", |result| result.score())
+    }
+
+    pub fn to_pgn(&self) -> PgnGame {
+        // The whole game, also the moves after the one being looked at
+        let moves = self
+            .history
+            .iter()
+            .chain(self.redo_stack.iter().rev())
+            .map(|entry| entry.san.clone())
+            .collect();
+        let mut game = PgnGame::new(moves, self.result());
+
+        let start_fen = self.start_fen.to_string();
+        if start_fen != fen::START_FEN {
+            game.set_tag("SetUp", "1");
+            game.set_tag("FEN", &start_fen);
+        }
+
+        if let Some(clock) = &self.clock {
+            game.set_tag("TimeControl", &clock.time_control.to_string());
+        }
+
+        game.set_tag("White", &self.pgn_name(Color::White));
+        game.set_tag("Black", &self.pgn_name(Color::Black));
+
+        if let Some(result) = self.game_result.or(self.browsed_result) {
+            game.set_tag("Termination", result.pgn_termination());
+        }
+
+        game
+    }
+
+    // People playing here are only known by name in network games, the PGN
+    // standard writes unknown names as "?"
+    fn pgn_name(&self, color: Color) -> String {
+        let player = &self.players[color_index(color)];
+        if !player.is_human() {
+            player.name()
+        } else if self.is_network_game() {
+            self.local_name.clone()
+        } else {
+            "?".to_string()
+        }
+    }
+
+    pub fn save_pgn(&mut self) {
+        let path = self
+            .options
+            .save_pgn
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("game.pgn"));
+
+        let status = match pgn::write_pgn_file(&path, &[self.to_pgn()]) {
+            Ok(()) => format!("Saved game to {}", path.display()),
+            Err(err) => err,
+        };
+
+        self.status = Some(status);
+    }
+
+    pub fn show_error(&mut self, error: String) {
+        eprintln!("{}", error);
+        self.error = Some(error);
+        self.error_animation.reset();
+    }
+
+    pub fn load_pgn_file(&mut self) {
+        let path = match &self.options.load_pgn {
+            Some(path) => path.clone(),
+            None => {
+                self.show_error("No PGN file to open, start with --load-pgn <path>".to_string());
+                return;
+            }
+        };
+
+        if let Err(err) = self.game_picker.read(&path) {
+            self.show_error(err);
+            return;
+        }
+
+        match self.game_picker.games.len() {
+            1 => self.load_game(0),
+            _ => self.game_picker.open(),
+        }
+    }
+
+    // Replaces the current game with the loaded game `index`, positioned
+    // before the first move so it can be stepped through with redo
+    pub fn load_game(&mut self, index: usize) {
+        let game = &self.game_picker.games[index];
+        let moves = match game.legal_moves(index, self.options.backend) {
+            Ok(moves) => moves,
+            Err(err) => {
+                self.show_error(format!("{}", err));
+                return;
+            }
+        };
+
+        let game_picker = std::mem::replace(&mut self.game_picker, GamePicker::new());
+        self.reset();
+        self.game_picker = game_picker;
+
+        let game = &self.game_picker.games[index];
+        if let Some(fen) = game.tag("FEN").map(|fen| fen.to_string()) {
+            self.set_position(&fen);
+        }
+
+        for mv in &moves {
+            if let Err(err) = self.apply_move(mv) {
+                self.show_error(err);
+                break;
+            }
+        }
+        self.go_to_start();
+
+        self.status = Some(format!("Loaded {}", self.game_picker.games[index].title()));
+    }
+
+    // Plays the moves of an opening book line, the clocks start afterwards
+    pub fn play_opening(&mut self, moves: &[Move]) -> Result<(), String> {
+        for mv in moves {
+            self.apply_move(mv)?;
+        }
+
+        self.animations = Vec::new();
+        self.captured = None;
+        self.clock = self.options.time_control.clone().map(Clock::new);
+        Ok(())
+    }
+
+    // Replaces the moves of the game with `moves` in long algebraic notation,
+    // played from the same start position without animating them
+    pub fn replay_uci_moves(&mut self, moves: &[String]) -> Result<(), String> {
+        let start_fen = self.start_fen.to_string();
+        if !self.set_position(&start_fen) {
+            return Err(format!("{} is not a valid position", start_fen));
+        }
+
+        for text in moves {
+            match uci::parse_uci_move(&self.board, text) {
+                Some(mv) => self.apply_move(&mv)?,
+                None => return Err(format!("{} is not a legal move", text)),
+            }
+        }
+
+        self.animations = Vec::new();
+        self.captured = None;
+        Ok(())
+    }
+
+    // Adds a move in long algebraic notation to the end of the game, without
+    // leaving the move that is being looked at
+    pub fn append_uci_move(&mut self, text: &str) -> Result<(), String> {
+        let ply = self.history.len();
+        self.go_to_end();
+
+        let mv = uci::parse_uci_move(&self.board, text)
+            .ok_or_else(|| format!("{} is not a legal move", text))?;
+        self.apply_move(&mv)?;
+
+        if self.history.len() > ply + 1 {
+            while self.history.len() > ply {
+                self.undo();
+            }
+            self.animations = Vec::new();
+            self.captured = None;
+        }
+
+        Ok(())
+    }
+
+    // Starts a new game from the position `fen`, errors are shown in the overlay
+    pub fn set_position(&mut self, fen: &str) -> bool {
+        let (fen, board) = match Fen::parse(fen).and_then(|fen| {
+            let board = fen.to_board(self.options.backend)?;
+            Ok((fen, board))
+        }) {
+            Ok(position) => position,
+            Err(err) => {
+                self.show_error(format!("{}", err));
+                return false;
+            }
+        };
+
+        self.close_promotion_dialog();
+
+        self.board = board;
+        self.start_fen = fen.clone();
+        self.fen = fen;
+        self.history = Vec::new();
+        self.redo_stack = Vec::new();
+        self.animations = Vec::new();
+        self.captured = None;
+        self.last_from = None;
+        self.last_to = None;
+        self.dragging = false;
+        self.from = None;
+        self.moves = Vec::new();
+        self.game_result = None;
+        self.browsed_result = None;
+        self.draw_offer = None;
+        self.end_state_show = true;
+        self.clock = self.options.time_control.clone().map(Clock::new);
+        self.live_clock = None;
+
+        self.update_game_state();
+
+        true
+    }
+
+    pub fn copy_fen(&mut self) {
+        let fen = self.fen.to_string();
+        self.status = Some(match clipboard::set(&fen) {
+            Ok(()) => "Copied FEN to the clipboard".to_string(),
+            Err(err) => err,
+        });
+    }
+
+    pub fn paste_fen(&mut self) {
+        let text = match clipboard::get() {
+            Ok(text) => text,
+            Err(err) => {
+                self.show_error(err);
+                return;
+            }
+        };
+
+        let text = text.trim().to_string();
+        if self.set_position(&text) {
+            // Restarting with R should go back to the pasted position
+            self.options.fen = Some(text);
+            self.status = Some("Loaded FEN from the clipboard".to_string());
+        }
+    }
+
+    fn restore(&mut self, entry: &HistoryEntry) {
+        self.board = entry.board.clone();
+        self.fen = entry.fen.clone();
+        self.last_from = entry.last_from;
+        self.last_to = entry.last_to;
+        self.check = entry.check;
+
+        // The clock of the game in play is put aside while earlier positions
+        // show the time that was left then
+        let clock = std::mem::replace(&mut self.clock, entry.clock.clone());
+        if self.live_clock.is_none() {
+            self.live_clock = clock;
+        }
+
+        // Taking back moves of a finished game lets it go on, the result is
+        // put aside until the last move is replayed
+        if !self.is_network_game() {
+            if let Some(result) = self.game_result.take() {
+                self.browsed_result = Some(result);
+            }
+        }
+
+        self.auto_flip_board();
+        self.update_draw_claim();
+
+        // A finished network game stays finished while its moves are looked
+        // through, the result card makes way for the board
+        if self.game_result.is_some() && self.end_state_show {
+            self.end_state_show = false;
+            self.end_state_animation.reset();
+        }
+
+        self.dragging = false;
+        self.from = None;
+        self.moves = Vec::new();
+    }
+
+    pub fn undo(&mut self) {
+        self.close_promotion_dialog();
+
+        let entry = match self.history.pop() {
+            Some(entry) => entry,
+            None => return,
+        };
+
+        self.restore(&entry);
+        self.captured = None;
+
+        // Play the move backwards, the pieces are already back on their
+        // original squares so the animations end there
+        self.animations = self
+            .move_animations(&entry.mv)
+            .into_iter()
+            .map(|a| {
+                AnimatePosition::new()
+                    .duration(0.2)
+                    .timing_function(AnimationTimingFunction::Ease)
+                    .start(a.end)
+                    .end(a.start)
+            })
+            .collect();
+
+        self.redo_stack.push(entry);
+    }
+
+    // Undoes moves until it is a human's turn again, so that playing against
+    // an engine does not immediately replay the move that was taken back.
+    // Spectators have no human side and step through every move
+    pub fn take_back(&mut self) {
+        self.undo();
+
+        if self.has_human() && !self.human_to_move() && !self.history.is_empty() {
+            self.undo();
+        }
+    }
+
+    pub fn replay_move(&mut self) {
+        self.redo();
+
+        if self.has_human() && !self.human_to_move() && !self.redo_stack.is_empty() {
+            self.redo();
+        }
+    }
+
+    fn has_human(&self) -> bool {
+        self.players.iter().any(|player| player.is_human())
+    }
+
+    pub fn go_to_start(&mut self) {
+        self.close_promotion_dialog();
+
+        while let Some(entry) = self.history.pop() {
+            self.redo_stack.push(entry);
+        }
+
+        if let Some(entry) = self.redo_stack.last().cloned() {
+            self.restore(&entry);
+        }
+
+        self.animations = Vec::new();
+        self.captured = None;
+    }
+
+    pub fn go_to_end(&mut self) {
+        self.close_promotion_dialog();
+
+        while let Some(entry) = self.redo_stack.pop() {
+            self.clock = entry.clock.clone();
+            if let Err(err) = self.apply_move(&entry.mv) {
+                self.redo_stack.push(entry);
+                self.show_error(err);
+                break;
+            }
+        }
+        self.show_browsed_clock();
+        self.show_browsed_result();
+
+        self.animations = Vec::new();
+        self.captured = None;
+    }
+
+    pub fn redo(&mut self) {
+        self.close_promotion_dialog();
+
+        if let Some(entry) = self.redo_stack.pop() {
+            self.clock = entry.clock.clone();
+            if let Err(err) = self.apply_move(&entry.mv) {
+                self.redo_stack.push(entry);
+                self.show_error(err);
+                return;
+            }
+            self.show_browsed_clock();
+            self.show_browsed_result();
+        }
+    }
+
+    // Back at the last move a game that was over is over again
+    fn show_browsed_result(&mut self) {
+        if !self.redo_stack.is_empty() {
+            return;
+        }
+
+        if let Some(result) = self.browsed_result.take() {
+            if self.game_result != Some(result) {
+                self.end_game(result);
+            }
+        }
+    }
+
+    // Shows the time that was left at the position, back at the last move
+    // the clock of the game in play takes over again
+    fn show_browsed_clock(&mut self) {
+        match self.redo_stack.last() {
+            Some(next) => self.clock = next.clock.clone(),
+            None => {
+                if let Some(clock) = self.live_clock.take() {
+                    self.clock = Some(clock);
+                }
+            }
+        }
+    }
+
+    // The side to move at the last position of the game
+    fn live_turn(&self) -> Color {
+        match self.redo_stack.first() {
+            Some(last) => fen::opposite(last.fen.turn),
+            None => self.board.whose_turn(),
+        }
+    }
+
+    // The clock of the game in play, also while earlier positions are shown
+    pub fn live_clock(&self) -> Option<&Clock> {
+        self.live_clock.as_ref().or(self.clock.as_ref())
+    }
+
+    pub fn live_clock_mut(&mut self) -> Option<&mut Clock> {
+        match &mut self.live_clock {
+            Some(clock) => Some(clock),
+            None => self.clock.as_mut(),
+        }
+    }
+
+    pub fn update(&mut self, args: &UpdateArgs) {
+        if self.game_result.is_some() {
+            self.end_state_animation.tick_dt(args.dt);
+        } else if self.browsed_result.is_none() {
+            // Time runs for the side to move in the game in play, also while
+            // an earlier position is looked at
+            let turn = self.live_turn();
+            let flagged = match self.live_clock_mut() {
+                Some(clock) => {
+                    clock.tick(turn, args.dt);
+                    clock.is_flagged(turn)
+                }
+                None => false,
+            };
+
+            if flagged && self.decides_timeouts() {
+                self.go_to_end();
+                self.flag();
+            }
+        }
+
+        self.promotion_animation.tick_dt(args.dt);
+        self.error_animation.tick_dt(args.dt);
+        self.game_picker.animation.tick_dt(args.dt);
+        self.dialog.animation.tick_dt(args.dt);
+        self.waiting_animation.tick_dt(args.dt);
+
+        // Nothing left to resign or offer once the game is over
+        if self.game_result.is_some() {
+            self.draw_offer = None;
+            self.dialog.close();
+        }
+
+        for a in self.animations.iter_mut() {
+            a.tick_dt(args.dt);
+        }
+
+        self.animations.retain(|a| !a.is_done());
+
+        self.captured_animation.tick_dt(args.dt);
+        if self.captured_animation.is_done() {
+            self.captured = None;
+        }
+
+        if self.move_input.focused {
+            self.move_input.refresh(&self.board, self.fen.to_string());
+        }
+
+        self.update_players();
+    }
+
+    pub fn human_to_move(&self) -> bool {
+        self.players[color_index(self.board.whose_turn())].is_human()
+    }
+
+    // Asks the side to move for its move
+    fn update_players(&mut self) {
+        let turn = color_index(self.board.whose_turn());
+
+        // Only the side to move may think, and nobody once the game is over
+        self.players[1 - turn].cancel();
+        if self.game_result.is_some() {
+            self.players[turn].cancel();
+            return;
+        }
+
+        // Browsing back through the game pauses it
+        if !self.animations.is_empty()
+            || !self.redo_stack.is_empty()
+            || self.players[turn].is_human()
+        {
+            return;
+        }
+
+        let moves = self
+            .history
+            .iter()
+            .map(|entry| entry.mv.clone())
+            .collect::<Vec<Move>>();
+        let hashes = self
+            .history
+            .iter()
+            .map(|entry| entry.hash)
+            .collect::<Vec<u64>>();
+
+        let game = GameView {
+            board: &self.board,
+            start_fen: &self.start_fen,
+            fen: &self.fen,
+            moves: &moves,
+            hashes: &hashes,
+            clock: self.clock.as_ref(),
+        };
+
+        match self.players[turn].poll_move(&game) {
+            Ok(Some(mv)) => self.make_move(&mv),
+            Ok(None) => {}
+            // The other side of a network game can not be played from here,
+            // it loses instead
+            Err(err) if self.is_network_game() => {
+                let winner = fen::opposite(self.board.whose_turn());
+                self.end_game(GameResult::win(winner, Termination::Abandonment));
+                self.show_error(err);
+            }
+            Err(err) => {
+                // Let a human take over the side that can not go on
+                self.players[turn] = Box::new(HumanPlayer);
+                self.show_error(err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::TimeControl;
+
+    fn play(controller: &mut ChessController, moves: &[&str]) {
+        for text in moves {
+            let mv = san::parse_san(controller.board.as_ref(), text)
+                .unwrap_or_else(|err| panic!("{}: {}", text, err));
+            controller.make_move(&mv);
+        }
+    }
+
+    fn fools_mate() -> ChessController {
+        let mut controller = ChessController::new(Options::new()).unwrap();
+        play(&mut controller, &["f3", "e5", "g4", "Qh4#"]);
+        assert!(controller.game_result == Some(black_mates()));
+        controller
+    }
+
+    fn black_mates() -> GameResult {
+        GameResult::win(Color::Black, Termination::Checkmate)
+    }
+
+    #[test]
+    fn undoing_a_mate_lets_the_game_go_on() {
+        let mut controller = fools_mate();
+
+        controller.undo();
+        assert!(controller.game_result.is_none());
+        assert_eq!(controller.result(), "0-1");
+
+        play(&mut controller, &["Nc6"]);
+        assert!(controller.game_result.is_none());
+        assert!(controller.redo_stack.is_empty());
+        assert_eq!(controller.result(), "*");
+        assert_eq!(controller.san_moves(), ["f3", "e5", "g4", "Nc6"]);
+
+        play(&mut controller, &["Nc3"]);
+        assert_eq!(controller.history.len(), 5);
+    }
+
+    #[test]
+    fn replaying_to_the_end_ends_the_game_again() {
+        let mut controller = fools_mate();
+
+        controller.go_to_start();
+        assert!(controller.game_result.is_none());
+
+        controller.go_to_end();
+        assert!(controller.game_result == Some(black_mates()));
+
+        controller.undo();
+        controller.redo();
+        assert!(controller.game_result.is_some());
+    }
+
+    #[test]
+    fn a_new_game_has_to_be_confirmed() {
+        let mut controller = fools_mate();
+
+        controller.open_dialog(Dialog::NewGame);
+        controller.answer_dialog(false);
+        assert_eq!(controller.history.len(), 4);
+
+        controller.open_dialog(Dialog::NewGame);
+        controller.answer_dialog(true);
+        assert!(controller.history.is_empty());
+        assert!(controller.game_result.is_none());
+    }
+
+    #[test]
+    fn the_clock_runs_while_browsing() {
+        let mut options = Options::new();
+        options.time_control = Some(TimeControl::parse("60").unwrap());
+        let mut controller = ChessController::new(options).unwrap();
+        play(&mut controller, &["e4", "e5"]);
+
+        controller.take_back();
+        controller.update(&UpdateArgs { dt: 1.0 });
+
+        // White is to move in the game, Black in the position looked at
+        assert_eq!(controller.live_clock().unwrap().remaining(Color::White), 59.0);
+        assert_eq!(controller.clock.as_ref().unwrap().remaining(Color::Black), 60.0);
+    }
+
+    fn claims_draw(controller: &ChessController) -> bool {
+        let status = controller.status.as_deref().unwrap_or("");
+        status.starts_with(CLAIM_DRAW)
+    }
+
+    fn from_fen(fen: &str) -> ChessController {
+        let mut options = Options::new();
+        options.fen = Some(fen.to_string());
+        ChessController::new(options).unwrap()
+    }
+
+    const KNIGHTS_OUT_AND_BACK: [&str; 4] = ["Nf3", "Nf6", "Ng1", "Ng8"];
+
+    #[test]
+    fn threefold_repetition_can_be_claimed() {
+        let mut controller = ChessController::new(Options::new()).unwrap();
+        play(&mut controller, &KNIGHTS_OUT_AND_BACK);
+        assert_eq!(controller.repetitions(), 2);
+        assert!(controller.draw_claim.is_none());
+
+        play(&mut controller, &KNIGHTS_OUT_AND_BACK);
+        assert_eq!(controller.repetitions(), 3);
+        assert!(controller.draw_claim == Some(Termination::Repetition));
+        assert!(claims_draw(&controller));
+
+        controller.claim_draw();
+        assert!(controller.game_result == Some(GameResult::draw(Termination::Repetition)));
+        assert!(!claims_draw(&controller));
+    }
+
+    #[test]
+    fn the_claim_goes_away_when_the_position_changes() {
+        let mut controller = ChessController::new(Options::new()).unwrap();
+        play(&mut controller, &KNIGHTS_OUT_AND_BACK);
+        play(&mut controller, &KNIGHTS_OUT_AND_BACK);
+        assert!(claims_draw(&controller));
+
+        play(&mut controller, &["e4"]);
+        assert!(controller.draw_claim.is_none());
+        assert!(controller.status.is_none());
+    }
+
+    #[test]
+    fn fivefold_repetition_ends_the_game() {
+        let mut controller = ChessController::new(Options::new()).unwrap();
+        for _ in 0..3 {
+            play(&mut controller, &KNIGHTS_OUT_AND_BACK);
+        }
+        assert!(controller.game_result.is_none());
+
+        play(&mut controller, &KNIGHTS_OUT_AND_BACK);
+        assert_eq!(controller.repetitions(), 5);
+        let fivefold = GameResult::draw(Termination::FivefoldRepetition);
+        assert!(controller.game_result == Some(fivefold));
+    }
+
+    #[test]
+    fn fifty_moves_can_be_claimed_until_a_capture() {
+        let mut controller = from_fen("4k3/8/8/8/8/8/r7/R3K3 w - - 98 80");
+        play(&mut controller, &["Kf1"]);
+        assert!(controller.draw_claim.is_none());
+
+        play(&mut controller, &["Kd7"]);
+        assert!(controller.draw_claim == Some(Termination::FiftyMoves));
+        assert!(claims_draw(&controller));
+
+        play(&mut controller, &["Rxa2"]);
+        assert!(controller.draw_claim.is_none());
+        assert!(!claims_draw(&controller));
+        assert!(controller.game_result.is_none());
+    }
+
+    #[test]
+    fn seventy_five_moves_end_the_game() {
+        let mut controller = from_fen("4k3/8/8/8/8/8/r7/R3K3 w - - 149 80");
+        play(&mut controller, &["Kf1"]);
+
+        let result = GameResult::draw(Termination::SeventyFiveMoves);
+        assert!(controller.game_result == Some(result));
+        assert!(controller.draw_claim.is_none());
+    }
+
+    #[test]
+    fn unknown_players_are_saved_as_question_marks() {
+        let mut controller = ChessController::new(Options::new()).unwrap();
+        play(&mut controller, &["e4"]);
+
+        let text = controller.to_pgn().to_pgn();
+        assert!(text.contains("[White \"?\"]\n[Black \"?\"]\n"), "{}", text);
+    }
+
+    #[test]
+    fn the_local_network_player_is_saved_by_name() {
+        let mut options = Options::new();
+        options.network = Some(NetworkRole::Host(0, Color::White));
+        let controller = ChessController::new(options).unwrap();
+
+        let name = std::env::var("USER").unwrap_or_else(|_| "Player".to_string());
+        assert_eq!(controller.pgn_name(Color::White), name);
     }
 }
@@ -12,6 +12,10 @@ use ChessAPI::piece::*;
 
 use crate::animation::Animation;
 use crate::chess_controller;
+use crate::clock;
+use crate::layout::Layout;
+use crate::rules;
+use crate::text_input::TextInput;
 
 pub struct ChessRenderer {
     gl: GlGraphics, // OpenGL drawing backend.
@@ -116,20 +120,29 @@ impl ChessRenderer {
         args: &RenderArgs,
         chess_controller: &chess_controller::ChessController,
     ) {
+        use graphics::character::CharacterCache;
         use graphics::*;
 
+        const BACKGROUND_COLOR: [f32; 4] = [0.1, 0.1, 0.1, 1.0];
         const BLACK_SQUARE_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
         const WHITE_SQUARE_COLOR: [f32; 4] = [0.65, 0.65, 0.65, 1.0];
         const SELECT_COLOR: [f32; 4] = [105.0 / 255.0, 148.0 / 255.0, 111.0 / 255.0, 1.0];
         const CAPTURE_COLOR: [f32; 4] = [148.0 / 255.0, 105.0 / 255.0, 111.0 / 255.0, 1.0];
         const LAST_MOVE_COLOR: [f32; 4] = [247.0 / 255.0, 233.0 / 255.0, 121.0 / 255.0, 0.5];
 
-        let width = args.window_size[0];
+        let layout = chess_controller.layout(args.window_size);
+        let coords = layout.board_coords(chess_controller.flipped);
+        let width = coords.size;
+        let [center_x, center_y] = layout.board_center();
+        let at = |c: &Context, pos: (f64, f64)| {
+            let [x, y] = coords.to_screen(pos);
+            c.transform.trans(x, y)
+        };
 
-        let size = width / 8.0;
+        let size = coords.square_size();
         let ellipse_size = size / 3.5;
 
-        let screen = rectangle::square(0.0, 0.0, width);
+        let screen = layout.window;
         let square = rectangle::square(0.0, 0.0, size);
         let ellipse_square = rectangle::square(
             (size - ellipse_size) / 2.0,
@@ -140,52 +153,75 @@ impl ChessRenderer {
 
         self.gl.draw(args.viewport(), |c, gl| {
             // Clear the screen.
-            clear(BLACK_SQUARE_COLOR, gl);
+            clear(BACKGROUND_COLOR, gl);
+            rectangle(BLACK_SQUARE_COLOR, coords.rect(), c.transform, gl);
             for x in 0..8 {
                 for y in 0..8 {
                     let x_f = x as f64;
                     let y_f = y as f64;
 
                     if x_f % 2.0 == y_f % 2.0 {
-                        let transform = c.transform.trans(x_f * size, y_f * size);
-                        rectangle(WHITE_SQUARE_COLOR, square, transform, gl);
+                        rectangle(WHITE_SQUARE_COLOR, square, at(&c, (x_f, y_f)), gl);
                     }
                     if Some((x, y)) == chess_controller.last_to
                         || Some((x, y)) == chess_controller.last_from
                     {
-                        let transform = c.transform.trans((x_f as f64) * size, (y_f as f64) * size);
-                        rectangle(LAST_MOVE_COLOR, square, transform, gl);
+                        rectangle(LAST_MOVE_COLOR, square, at(&c, (x_f, y_f)), gl);
                     }
                 }
             }
 
             if let Some((x, y)) = chess_controller.check {
-                let transform = c.transform.trans((x as f64) * size, (y as f64) * size);
-                rectangle(CAPTURE_COLOR, square, transform, gl);
+                rectangle(CAPTURE_COLOR, square, at(&c, (x as f64, y as f64)), gl);
             }
 
             if let Some((x, y)) = chess_controller.from {
-                let transform = c.transform.trans((x as f64) * size, (y as f64) * size);
-                rectangle(SELECT_COLOR, square, transform, gl);
+                rectangle(SELECT_COLOR, square, at(&c, (x as f64, y as f64)), gl);
             }
 
             chess_controller.moves.iter().for_each(|mv| {
-                if chess_controller.board.get_board()[mv.to.row as usize][mv.to.col as usize]
-                    .is_some()
-                {
+                if rules::classify(&chess_controller.board, mv).is_capture() {
                     let x = mv.to.col as f64;
                     let y = mv.to.row as f64;
 
-                    let transform = c.transform.trans((x as f64) * size, (y as f64) * size);
-                    rectangle(CAPTURE_COLOR, square, transform, gl);
+                    rectangle(CAPTURE_COLOR, square, at(&c, (x, y)), gl);
                 } else {
                     let x = mv.to.col as f64;
                     let y = mv.to.row as f64;
-                    let transform = c.transform.trans(x * size, y * size);
-                    ellipse(SELECT_COLOR, ellipse_square, transform, gl);
+                    ellipse(SELECT_COLOR, ellipse_square, at(&c, (x, y)), gl);
                 }
             });
 
+            // Outline the square a held piece would be dropped on
+            if let (true, Some((x, y))) = (
+                chess_controller.dragging,
+                coords.square_at(chess_controller.cursor_pos),
+            ) {
+                if chess_controller
+                    .moves
+                    .iter()
+                    .any(|mv| (mv.to.col as usize, mv.to.row as usize) == (x, y))
+                {
+                    rectangle::Rectangle::new_border(SELECT_COLOR, 3.0).draw(
+                        [3.0, 3.0, size - 6.0, size - 6.0],
+                        &Default::default(),
+                        at(&c, (x as f64, y as f64)),
+                        gl,
+                    );
+                }
+            }
+
+            if let Some((piece, (x, y))) = chess_controller.captured {
+                let t = chess_controller.captured_animation.value() as f32;
+
+                image.clone().color([1.0, 1.0, 1.0, 1.0 - t]).draw(
+                    self.textures.piece_to_texture(&piece),
+                    &graphics::draw_state::DrawState::default(),
+                    at(&c, (x as f64, y as f64)),
+                    gl,
+                );
+            }
+
             for x in 0..8 {
                 for y in 0..8 {
                     let x = x as f64;
@@ -201,18 +237,82 @@ impl ChessRenderer {
                         }
                     }
 
+                    // The held piece is drawn at the cursor instead
+                    if chess_controller.dragging
+                        && chess_controller.from == Some((x as usize, y as usize))
+                    {
+                        continue;
+                    }
+
                     if let Some(piece) = chess_controller.board.get_board()[y as usize][x as usize]
                     {
                         image.draw(
                             self.textures.piece_to_texture(&piece),
                             &graphics::draw_state::DrawState::default(),
-                            c.transform.trans(x * size, y * size),
+                            at(&c, (x, y)),
                             gl,
                         );
                     }
                 }
             }
 
+            draw_move_list(
+                &c,
+                gl,
+                &mut self.glyphs_regular,
+                layout.move_list,
+                chess_controller,
+            );
+
+            // Dimmed when there is nobody to resign or offer a draw for
+            let available = chess_controller.can_resign_or_offer();
+            let offer_available = available && chess_controller.draw_offer.is_none();
+            let actions = [("Resign", available), ("Offer draw", offer_available)];
+            for ((label, available), button) in actions.iter().zip(layout.action_buttons()) {
+                let label_color = match available {
+                    true => [0.0, 0.0, 0.0, 1.0],
+                    false => [0.55, 0.55, 0.55, 1.0],
+                };
+                draw_button(
+                    &c,
+                    gl,
+                    &mut self.glyphs_regular,
+                    button,
+                    label,
+                    label_color,
+                    1.0,
+                );
+            }
+
+            if chess_controller.move_input_shown() {
+                draw_move_input(&c, gl, &mut self.glyphs_regular, &layout, chess_controller);
+            }
+
+            if chess_controller.chat_shown() {
+                draw_chat(&c, gl, &mut self.glyphs_regular, &layout, chess_controller);
+            }
+
+            if let Some(clock) = &chess_controller.clock {
+                let turn = chess_controller.board.whose_turn();
+                let running = clock.running && chess_controller.game_result.is_none();
+
+                let (top, bottom) = match chess_controller.flipped {
+                    true => (Color::White, Color::Black),
+                    false => (Color::Black, Color::White),
+                };
+
+                for (rect, color) in [(layout.top_clock, top), (layout.bottom_clock, bottom)] {
+                    draw_clock(
+                        &c,
+                        gl,
+                        &mut self.glyphs_medium,
+                        rect,
+                        clock.remaining(color),
+                        running && turn == color,
+                    );
+                }
+            }
+
             if !chess_controller.animations.is_empty() {
                 for animation in chess_controller.animations.clone() {
                     let (x, y) = animation.pos();
@@ -224,7 +324,21 @@ impl ChessRenderer {
                     image.draw(
                         self.textures.piece_to_texture(&piece),
                         &graphics::draw_state::DrawState::default(),
-                        c.transform.trans(x * size, y * size),
+                        at(&c, (x, y)),
+                        gl,
+                    );
+                }
+            }
+
+            if let (true, Some((x, y))) = (chess_controller.dragging, chess_controller.from) {
+                if let Some(piece) = chess_controller.board.get_board()[y][x] {
+                    let [cursor_x, cursor_y] = chess_controller.cursor_pos;
+
+                    image.draw(
+                        self.textures.piece_to_texture(&piece),
+                        &graphics::draw_state::DrawState::default(),
+                        c.transform
+                            .trans(cursor_x - size / 2.0, cursor_y - size / 2.0),
                         gl,
                     );
                 }
@@ -242,10 +356,7 @@ impl ChessRenderer {
                 let rect = rectangle::Rectangle::new_round([0.95, 0.95, 0.95, 1.0 * t], 5.0);
 
                 for i in 0..4 {
-                    let gap = 10.0;
-
-                    let x = width / 2.0 - (size + gap) * 2.0 + gap / 2.0 + (size + gap) * i as f64;
-                    let y = width / 2.0 - size / 2.0;
+                    let [x, y] = coords.promotion_option(i);
                     let trans = c.transform.trans(x, y);
 
                     rect.draw(square, &Default::default(), trans, gl);
@@ -265,7 +376,7 @@ impl ChessRenderer {
                 }
             }
 
-            if let Some(state) = chess_controller.end_state {
+            if let Some(result) = chess_controller.game_result {
                 let t = chess_controller.end_state_animation.value() as f32;
 
                 let t = match chess_controller.end_state_show {
@@ -276,38 +387,803 @@ impl ChessRenderer {
                 rectangle([0.0, 0.0, 0.0, 0.9 * t], screen, c.transform, gl);
                 let rect = rectangle::Rectangle::new_round([0.95, 0.95, 0.95, 1.0 * t], 5.0);
 
-                rect.draw(
-                    rectangle::centered([width / 2.0, width / 2.0, width / 3.0, width / 10.0]),
+                let card = layout.result_card();
+                rect.draw(card, &Default::default(), c.transform, gl);
+
+                // The board can be any size, so all text is centered by
+                // measuring it
+                let lines = [
+                    (result.title(), 32, [0.0, 0.0, 0.0, 1.0 * t], 0.27),
+                    (
+                        result.description().to_string(),
+                        16,
+                        [0.3, 0.3, 0.3, 1.0 * t],
+                        0.41,
+                    ),
+                    (
+                        result.score().to_string(),
+                        20,
+                        [0.0, 0.0, 0.0, 1.0 * t],
+                        0.58,
+                    ),
+                ];
+
+                for (i, (content, size, color, y)) in lines.iter().enumerate() {
+                    let glyphs = match i {
+                        1 => &mut self.glyphs_regular,
+                        _ => &mut self.glyphs_medium,
+                    };
+                    let content_width = glyphs.width(*size, content).unwrap_or(0.0);
+
+                    Text::new_color(*color, *size)
+                        .draw_pos(
+                            content,
+                            [center_x - content_width / 2.0, card[1] + card[3] * y],
+                            glyphs,
+                            &Default::default(),
+                            c.transform,
+                            gl,
+                        )
+                        .unwrap();
+                }
+
+                let buttons = layout.result_buttons();
+                for (action, button) in chess_controller::ResultAction::ALL.iter().zip(buttons) {
+                    let glyphs = &mut self.glyphs_regular;
+                    let label_color = [0.0, 0.0, 0.0, 1.0 * t];
+                    draw_button(&c, gl, glyphs, button, action.label(), label_color, t);
+                }
+            }
+
+            if let Some(message) = &chess_controller.waiting {
+                let t = chess_controller.waiting_animation.value() as f32;
+                let t = match chess_controller.waiting_open {
+                    true => t,
+                    false => 1.0 - t,
+                };
+
+                if t > 0.0 {
+                    rectangle([0.0, 0.0, 0.0, 0.9 * t], screen, c.transform, gl);
+
+                    let card = layout.dialog(0.66, 0.3);
+                    rectangle::Rectangle::new_round([0.95, 0.95, 0.95, 1.0 * t], 5.0).draw(
+                        card,
+                        &Default::default(),
+                        c.transform,
+                        gl,
+                    );
+
+                    let lines = [
+                        ("Waiting for opponent", 24, [0.0, 0.0, 0.0, 1.0 * t], 0.4),
+                        (message.as_str(), 14, [0.3, 0.3, 0.3, 1.0 * t], 0.65),
+                    ];
+
+                    for (i, (content, size, color, y)) in lines.iter().enumerate() {
+                        let glyphs = match i {
+                            1 => &mut self.glyphs_regular,
+                            _ => &mut self.glyphs_medium,
+                        };
+                        let content_width = glyphs.width(*size, content).unwrap_or(0.0);
+
+                        Text::new_color(*color, *size)
+                            .draw_pos(
+                                content,
+                                [center_x - content_width / 2.0, card[1] + card[3] * y],
+                                glyphs,
+                                &Default::default(),
+                                c.transform,
+                                gl,
+                            )
+                            .unwrap();
+                    }
+                }
+            }
+
+            if let Some(dialog) = chess_controller.dialog.dialog {
+                let t = chess_controller.dialog.animation.value() as f32;
+                let t = match chess_controller.dialog.open {
+                    true => t,
+                    false => 1.0 - t,
+                };
+
+                if t > 0.0 {
+                    rectangle([0.0, 0.0, 0.0, 0.9 * t], screen, c.transform, gl);
+
+                    let card = layout.dialog(0.6, 0.3);
+                    rectangle::Rectangle::new_round([0.95, 0.95, 0.95, 1.0 * t], 5.0).draw(
+                        card,
+                        &Default::default(),
+                        c.transform,
+                        gl,
+                    );
+
+                    let text = dialog.text();
+                    let text_width = self.glyphs_medium.width(24, &text).unwrap_or(0.0);
+
+                    Text::new_color([0.0, 0.0, 0.0, 1.0 * t], 24)
+                        .draw_pos(
+                            &text,
+                            [center_x - text_width / 2.0, card[1] + card[3] * 0.38],
+                            &mut self.glyphs_medium,
+                            &Default::default(),
+                            c.transform,
+                            gl,
+                        )
+                        .unwrap();
+
+                    let buttons = layout.confirm_buttons();
+                    for (label, button) in dialog.buttons().iter().zip(buttons) {
+                        let glyphs = &mut self.glyphs_regular;
+                        let label_color = [0.0, 0.0, 0.0, 1.0 * t];
+                        draw_button(&c, gl, glyphs, button, label, label_color, t);
+                    }
+                }
+            }
+
+            {
+                let t = chess_controller.game_picker.animation.value() as f32;
+                let t = match chess_controller.game_picker.open {
+                    true => t,
+                    false => 1.0 - t,
+                };
+
+                if t > 0.0 {
+                    draw_game_picker(
+                        &c,
+                        gl,
+                        &mut self.glyphs_regular,
+                        &layout,
+                        t,
+                        chess_controller,
+                    );
+                }
+            }
+
+            if let Some(error) = &chess_controller.error {
+                let t = chess_controller.error_animation.value() as f32;
+
+                rectangle([0.0, 0.0, 0.0, 0.9 * t], screen, c.transform, gl);
+                let rect = rectangle::Rectangle::new_round([0.95, 0.95, 0.95, 1.0 * t], 5.0);
+
+                let lines = wrap_text(error, 48);
+                let line_height = 22.0;
+                let card_height = line_height * lines.len() as f64 + 60.0;
+                let card =
+                    rectangle::centered([center_x, center_y, width * 0.4, card_height / 2.0]);
+
+                rect.draw(card, &Default::default(), c.transform, gl);
+
+                let text = Text::new_color([0.6, 0.1, 0.1, 1.0 * t], 16);
+                for (i, line) in lines.iter().enumerate() {
+                    text.draw_pos(
+                        line,
+                        [card[0] + 20.0, card[1] + 35.0 + line_height * i as f64],
+                        &mut self.glyphs_regular,
+                        &Default::default(),
+                        c.transform,
+                        gl,
+                    )
+                    .unwrap();
+                }
+
+                Text::new_color([0.4, 0.4, 0.4, 1.0 * t], 12)
+                    .draw_pos(
+                        "Press any key to continue",
+                        [card[0] + 20.0, card[1] + card[3] - 15.0],
+                        &mut self.glyphs_regular,
+                        &Default::default(),
+                        c.transform,
+                        gl,
+                    )
+                    .unwrap();
+            }
+        });
+    }
+
+    pub fn update(&mut self, args: &UpdateArgs) {}
+}
+
+fn draw_clock(
+    c: &graphics::Context,
+    gl: &mut GlGraphics,
+    glyphs: &mut GlyphCache<'static>,
+    rect: [f64; 4],
+    remaining: f64,
+    active: bool,
+) {
+    use graphics::*;
+
+    const CLOCK_COLOR: [f32; 4] = [0.15, 0.15, 0.15, 1.0];
+    const ACTIVE_CLOCK_COLOR: [f32; 4] = [0.95, 0.95, 0.95, 1.0];
+    const LOW_TIME_COLOR: [f32; 4] = [148.0 / 255.0, 105.0 / 255.0, 111.0 / 255.0, 1.0];
+
+    let [x, y, w, h] = rect;
+    let padding = 8.0;
+
+    let background = if active && remaining < 10.0 {
+        LOW_TIME_COLOR
+    } else if active {
+        ACTIVE_CLOCK_COLOR
+    } else {
+        CLOCK_COLOR
+    };
+
+    rectangle::Rectangle::new_round(background, 5.0).draw(
+        [
+            x + padding,
+            y + padding,
+            w - padding * 2.0,
+            h - padding * 2.0,
+        ],
+        &Default::default(),
+        c.transform,
+        gl,
+    );
+
+    let color = if active {
+        [0.0, 0.0, 0.0, 1.0]
+    } else {
+        [0.9, 0.9, 0.9, 1.0]
+    };
+
+    Text::new_color(color, 28)
+        .draw_pos(
+            &clock::format_time(remaining),
+            [x + padding * 3.0, y + h / 2.0 + 10.0],
+            glyphs,
+            &Default::default(),
+            c.transform,
+            gl,
+        )
+        .unwrap();
+}
+
+// Breaks text into lines of at most `max_chars` characters at word
+// boundaries, words that do not fit on a line of their own are split
+fn wrap_text(text: &str, max_chars: usize) -> Vec<String> {
+    let max_chars = max_chars.max(1);
+    let mut lines = Vec::new();
+
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > max_chars {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            for c in word.chars() {
+                if line.chars().count() >= max_chars {
+                    lines.push(std::mem::take(&mut line));
+                }
+                line.push(c);
+            }
+        }
+        lines.push(line);
+    }
+
+    lines
+}
+
+// A rounded button with its label centered
+fn draw_button(
+    c: &graphics::Context,
+    gl: &mut GlGraphics,
+    glyphs: &mut GlyphCache<'static>,
+    rect: [f64; 4],
+    label: &str,
+    label_color: [f32; 4],
+    t: f32,
+) {
+    use graphics::*;
+
+    rectangle::Rectangle::new_round([0.85, 0.85, 0.85, 1.0 * t], 4.0).draw(
+        rect,
+        &Default::default(),
+        c.transform,
+        gl,
+    );
+
+    let label_width = glyphs.width(16, label).unwrap_or(0.0);
+
+    Text::new_color(label_color, 16)
+        .draw_pos(
+            label,
+            [
+                rect[0] + (rect[2] - label_width) / 2.0,
+                rect[1] + rect[3] / 2.0 + 6.0,
+            ],
+            glyphs,
+            &Default::default(),
+            c.transform,
+            gl,
+        )
+        .unwrap();
+}
+
+// Messages from the bottom up, the newest last unless scrolled back
+fn draw_chat(
+    c: &graphics::Context,
+    gl: &mut GlGraphics,
+    glyphs: &mut GlyphCache<'static>,
+    layout: &Layout,
+    chess_controller: &chess_controller::ChessController,
+) {
+    use graphics::*;
+
+    const PANEL_COLOR: [f32; 4] = [0.17, 0.17, 0.17, 1.0];
+    const TEXT_COLOR: [f32; 4] = [0.9, 0.9, 0.9, 1.0];
+    const TIME_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
+
+    let chat = &chess_controller.chat;
+    let [x, y, w, _] = layout.chat;
+    let padding = 8.0;
+    let line_height = 16.0;
+
+    rectangle(PANEL_COLOR, layout.chat, c.transform, gl);
+
+    let title = match chess_controller.is_network_game() {
+        true => "Chat",
+        false => "Notes",
+    };
+    Text::new_color(TEXT_COLOR, 14)
+        .draw_pos(
+            title,
+            [x + padding, y + padding + 12.0],
+            glyphs,
+            &Default::default(),
+            c.transform,
+            gl,
+        )
+        .unwrap();
+
+    let input = layout.chat_input();
+    let top = y + padding * 2.0 + 12.0;
+    let mut baseline = input[1] - padding;
+
+    // Roughly the widest line that fits, the font is not monospaced
+    let max_chars = ((w - padding * 2.0) / 6.5).max(1.0) as usize;
+    let shown = chat.messages.len() - chat.scroll.min(chat.messages.len());
+
+    'messages: for message in chat.messages[..shown].iter().rev() {
+        let lines = wrap_text(&message.line(), max_chars);
+        for (i, line) in lines.iter().enumerate().rev() {
+            if baseline - line_height < top {
+                break 'messages;
+            }
+
+            // The time is dimmed on the first line of each message
+            let (time, rest) = match i {
+                0 => line.split_at(message.time.len().min(line.len())),
+                _ => ("", line.as_str()),
+            };
+            let time_width = glyphs.width(12, time).unwrap_or(0.0);
+
+            for (text, color, offset) in [(time, TIME_COLOR, 0.0), (rest, TEXT_COLOR, time_width)] {
+                Text::new_color(color, 12)
+                    .draw_pos(
+                        text,
+                        [x + padding + offset, baseline],
+                        glyphs,
+                        &Default::default(),
+                        c.transform,
+                        gl,
+                    )
+                    .unwrap();
+            }
+
+            baseline -= line_height;
+        }
+    }
+
+    let placeholder = match chat.focused {
+        true => "",
+        false => "Press Enter to write",
+    };
+    draw_text_input(c, gl, glyphs, input, &chat.input, placeholder, chat.focused);
+}
+
+// The field for typing moves, with the legal moves it could become or the
+// reason the last move was not played below it
+fn draw_move_input(
+    c: &graphics::Context,
+    gl: &mut GlGraphics,
+    glyphs: &mut GlyphCache<'static>,
+    layout: &Layout,
+    chess_controller: &chess_controller::ChessController,
+) {
+    use graphics::*;
+
+    const PANEL_COLOR: [f32; 4] = [0.2, 0.2, 0.2, 1.0];
+    const CANDIDATE_COLOR: [f32; 4] = [0.6, 0.6, 0.6, 1.0];
+    const NEXT_CANDIDATE_COLOR: [f32; 4] = [0.9, 0.9, 0.9, 1.0];
+    const ERROR_COLOR: [f32; 4] = [0.9, 0.55, 0.55, 1.0];
+
+    let move_input = &chess_controller.move_input;
+    let field = layout.move_field();
+    let [x, _, w, _] = layout.move_input;
+    let baseline = field[1] + field[3] + 16.0;
+
+    rectangle(PANEL_COLOR, layout.move_input, c.transform, gl);
+
+    let placeholder = match move_input.focused {
+        true => "Tab completes the move",
+        false => "Press Tab to type a move",
+    };
+    draw_text_input(
+        c,
+        gl,
+        glyphs,
+        field,
+        &move_input.input,
+        placeholder,
+        move_input.focused,
+    );
+
+    if let Some(error) = &move_input.error {
+        // Long errors are cut short, the panel has room for one line
+        let max_chars = ((w - 16.0) / 6.0).max(1.0) as usize;
+        let line = wrap_text(error, max_chars)
+            .into_iter()
+            .next()
+            .unwrap_or_default();
+
+        Text::new_color(ERROR_COLOR, 12)
+            .draw_pos(
+                &line,
+                [x + 8.0, baseline],
+                glyphs,
+                &Default::default(),
+                c.transform,
+                gl,
+            )
+            .unwrap();
+        return;
+    }
+
+    if !move_input.focused {
+        return;
+    }
+
+    // As many candidates as fit, starting with the one Tab fills in next
+    let count = move_input.candidates.len();
+    let mut offset = 0.0;
+    for i in 0..count {
+        let candidate = &move_input.candidates[(move_input.selected + i) % count];
+        let width = glyphs.width(12, candidate).unwrap_or(0.0);
+        if offset + width > w - 16.0 {
+            break;
+        }
+
+        let color = match i {
+            0 => NEXT_CANDIDATE_COLOR,
+            _ => CANDIDATE_COLOR,
+        };
+        Text::new_color(color, 12)
+            .draw_pos(
+                candidate,
+                [x + 8.0 + offset, baseline],
+                glyphs,
+                &Default::default(),
+                c.transform,
+                gl,
+            )
+            .unwrap();
+
+        offset += width + 10.0;
+    }
+}
+
+// A text field showing the end of the text when it is too long to fit, and
+// the cursor while it has the keyboard
+fn draw_text_input(
+    c: &graphics::Context,
+    gl: &mut GlGraphics,
+    glyphs: &mut GlyphCache<'static>,
+    rect: [f64; 4],
+    input: &TextInput,
+    placeholder: &str,
+    focused: bool,
+) {
+    use graphics::*;
+
+    const FOCUSED_COLOR: [f32; 4] = [105.0 / 255.0, 148.0 / 255.0, 111.0 / 255.0, 1.0];
+
+    let [x, y, w, h] = rect;
+    let padding = 6.0;
+    let font_size = 14;
+
+    rectangle::Rectangle::new_round([0.95, 0.95, 0.95, 1.0], 4.0).draw(
+        rect,
+        &Default::default(),
+        c.transform,
+        gl,
+    );
+    if focused {
+        rectangle::Rectangle::new_round_border(FOCUSED_COLOR, 4.0, 1.5).draw(
+            rect,
+            &Default::default(),
+            c.transform,
+            gl,
+        );
+    }
+
+    if input.text.is_empty() {
+        Text::new_color([0.5, 0.5, 0.5, 1.0], font_size)
+            .draw_pos(
+                placeholder,
+                [x + padding, y + h / 2.0 + 5.0],
+                glyphs,
+                &Default::default(),
+                c.transform,
+                gl,
+            )
+            .unwrap();
+    }
+
+    // Drop characters from the start until the cursor is inside the field
+    let mut start = 0;
+    let mut cursor_x = glyphs
+        .width(font_size, input.before_cursor())
+        .unwrap_or(0.0);
+    while cursor_x > w - padding * 2.0 && start < input.cursor {
+        start += input.text[start..]
+            .chars()
+            .next()
+            .map_or(1, |ch| ch.len_utf8());
+        cursor_x = glyphs
+            .width(font_size, &input.text[start..input.cursor])
+            .unwrap_or(0.0);
+    }
+
+    Text::new_color([0.0, 0.0, 0.0, 1.0], font_size)
+        .draw_pos(
+            &input.text[start..],
+            [x + padding, y + h / 2.0 + 5.0],
+            glyphs,
+            &Default::default(),
+            c.transform,
+            gl,
+        )
+        .unwrap();
+
+    if focused {
+        rectangle(
+            [0.0, 0.0, 0.0, 1.0],
+            [x + padding + cursor_x, y + padding, 1.0, h - padding * 2.0],
+            c.transform,
+            gl,
+        );
+    }
+}
+
+fn draw_game_picker(
+    c: &graphics::Context,
+    gl: &mut GlGraphics,
+    glyphs: &mut GlyphCache<'static>,
+    layout: &Layout,
+    t: f32,
+    chess_controller: &chess_controller::ChessController,
+) {
+    use graphics::*;
+
+    const SELECTED_COLOR: [f32; 4] = [105.0 / 255.0, 148.0 / 255.0, 111.0 / 255.0, 1.0];
+
+    rectangle([0.0, 0.0, 0.0, 0.9 * t], layout.window, c.transform, gl);
+
+    let card = layout.dialog(0.8, 0.8);
+    rectangle::Rectangle::new_round([0.95, 0.95, 0.95, 1.0 * t], 5.0).draw(
+        card,
+        &Default::default(),
+        c.transform,
+        gl,
+    );
+
+    Text::new_color([0.0, 0.0, 0.0, 1.0 * t], 20)
+        .draw_pos(
+            "Choose a game",
+            [card[0] + 20.0, card[1] + 35.0],
+            glyphs,
+            &Default::default(),
+            c.transform,
+            gl,
+        )
+        .unwrap();
+
+    let row_height = 26.0;
+    let list_top = card[1] + 55.0;
+    let visible_rows = ((card[3] - 90.0) / row_height).max(1.0) as usize;
+
+    let selected = chess_controller.game_picker.selected;
+    let first_row = if selected >= visible_rows {
+        selected + 1 - visible_rows
+    } else {
+        0
+    };
+
+    let games = chess_controller.game_picker.games.iter().enumerate();
+    for (i, game) in games.skip(first_row).take(visible_rows) {
+        let row_y = list_top + (i - first_row) as f64 * row_height;
+
+        let color = if i == selected {
+            rectangle::Rectangle::new_round(
+                [SELECTED_COLOR[0], SELECTED_COLOR[1], SELECTED_COLOR[2], t],
+                3.0,
+            )
+            .draw(
+                [card[0] + 10.0, row_y, card[2] - 20.0, row_height - 2.0],
+                &Default::default(),
+                c.transform,
+                gl,
+            );
+            [1.0, 1.0, 1.0, 1.0 * t]
+        } else {
+            [0.0, 0.0, 0.0, 1.0 * t]
+        };
+
+        Text::new_color(color, 14)
+            .draw_pos(
+                &format!("{}. {}", i + 1, game.title()),
+                [card[0] + 20.0, row_y + row_height - 8.0],
+                glyphs,
+                &Default::default(),
+                c.transform,
+                gl,
+            )
+            .unwrap();
+    }
+
+    Text::new_color([0.4, 0.4, 0.4, 1.0 * t], 12)
+        .draw_pos(
+            "Up/Down to choose, Enter to open, Escape to close",
+            [card[0] + 20.0, card[1] + card[3] - 15.0],
+            glyphs,
+            &Default::default(),
+            c.transform,
+            gl,
+        )
+        .unwrap();
+}
+
+fn draw_move_list(
+    c: &graphics::Context,
+    gl: &mut GlGraphics,
+    glyphs: &mut GlyphCache<'static>,
+    rect: [f64; 4],
+    chess_controller: &chess_controller::ChessController,
+) {
+    use graphics::*;
+
+    const PANEL_COLOR: [f32; 4] = [0.2, 0.2, 0.2, 1.0];
+    const TEXT_COLOR: [f32; 4] = [0.9, 0.9, 0.9, 1.0];
+    const FUTURE_TEXT_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
+    const CURRENT_MOVE_COLOR: [f32; 4] = [105.0 / 255.0, 148.0 / 255.0, 111.0 / 255.0, 1.0];
+
+    let [x, y, w, h] = rect;
+
+    let padding = 15.0;
+    let row_height = 24.0;
+    let number_width = 40.0;
+    let column_width = (w - padding * 2.0 - number_width) / 2.0;
+
+    rectangle(PANEL_COLOR, rect, c.transform, gl);
+
+    Text::new_color(TEXT_COLOR, 20)
+        .draw_pos(
+            "Moves",
+            [x + padding, y + padding + 20.0],
+            glyphs,
+            &Default::default(),
+            c.transform,
+            gl,
+        )
+        .unwrap();
+
+    let moves = chess_controller.san_moves();
+    let current = chess_controller.history.len();
+
+    // Positions set up from a FEN can start with Black to move, which leaves
+    // the first white column empty
+    let offset = (chess_controller.start_fen.turn == Color::Black) as usize;
+    let first_move = chess_controller.start_fen.fullmove as usize;
+
+    let status_height = 30.0;
+    let list_top = y + padding * 2.0 + 20.0;
+    let visible_rows =
+        ((h - (list_top - y) - padding - status_height) / row_height).max(0.0) as usize;
+    let rows = (moves.len() + offset + 1) / 2;
+
+    // Scroll so that the current move is always visible
+    let current_row = (current + offset).saturating_sub(1) / 2;
+    let first_row = if current_row >= visible_rows {
+        current_row + 1 - visible_rows
+    } else {
+        0
+    };
+
+    for row in first_row..rows.min(first_row + visible_rows) {
+        let row_y = list_top + (row - first_row) as f64 * row_height;
+        let baseline = row_y + row_height - 7.0;
+
+        Text::new_color(FUTURE_TEXT_COLOR, 16)
+            .draw_pos(
+                &format!("{}.", first_move + row),
+                [x + padding, baseline],
+                glyphs,
+                &Default::default(),
+                c.transform,
+                gl,
+            )
+            .unwrap();
+
+        for side in 0..2 {
+            if row * 2 + side < offset {
+                continue;
+            }
+
+            let ply = row * 2 + side - offset;
+            if ply >= moves.len() {
+                break;
+            }
+
+            let column_x = x + padding + number_width + side as f64 * column_width;
+
+            if ply + 1 == current {
+                rectangle::Rectangle::new_round(CURRENT_MOVE_COLOR, 3.0).draw(
+                    [
+                        column_x - 5.0,
+                        row_y + 2.0,
+                        column_width - 5.0,
+                        row_height - 4.0,
+                    ],
                     &Default::default(),
                     c.transform,
                     gl,
                 );
+            }
 
-                let text = Text::new_color([0.0, 0.0, 0.0, 1.0 * t], 32);
-
-                let black_won_offset = 7.1;
-                let white_won_offset = 7.05;
-                let stalemate_offset = 7.23;
-
-                let (offset, content) = match state {
-                    2 => (white_won_offset, "White won"),
-                    1 => (black_won_offset, "Black won"),
-                    0 => (stalemate_offset, "Stalemate"),
-                    _ => unreachable!(),
-                };
+            let color = if ply < current {
+                TEXT_COLOR
+            } else {
+                FUTURE_TEXT_COLOR
+            };
 
-                text.draw_pos(
-                    content,
-                    [width / 2.0, width / 2.0],
-                    &mut self.glyphs_medium,
+            Text::new_color(color, 16)
+                .draw_pos(
+                    moves[ply],
+                    [column_x, baseline],
+                    glyphs,
                     &Default::default(),
-                    c.transform.trans(-width / offset, 10.0),
+                    c.transform,
                     gl,
                 )
                 .unwrap();
-            }
-        });
+        }
     }
 
-    pub fn update(&mut self, args: &UpdateArgs) {}
+    if let Some(status) = &chess_controller.status {
+        Text::new_color(FUTURE_TEXT_COLOR, 12)
+            .draw_pos(
+                status,
+                [x + padding, y + h - padding],
+                glyphs,
+                &Default::default(),
+                c.transform,
+                gl,
+            )
+            .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_words_are_split() {
+        assert_eq!(wrap_text("a bb ccc", 4), ["a bb", "ccc"]);
+        assert_eq!(wrap_text("ab abcdefghij", 4), ["ab", "abcd", "efgh", "ij"]);
+        assert_eq!(wrap_text("one\n\ntwo", 10), ["one", "", "two"]);
+    }
 }
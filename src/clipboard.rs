@@ -0,0 +1,66 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+// There is no clipboard access through the window, so the platform's
+// clipboard tools are used instead
+const PASTE_COMMANDS: &[&[&str]] = &[
+    &["pbpaste"],
+    &["powershell", "-NoProfile", "-Command", "Get-Clipboard"],
+    &["wl-paste", "--no-newline"],
+    &["xclip", "-selection", "clipboard", "-o"],
+    &["xsel", "--clipboard", "--output"],
+];
+
+const COPY_COMMANDS: &[&[&str]] = &[
+    &["pbcopy"],
+    &["clip"],
+    &["wl-copy"],
+    &["xclip", "-selection", "clipboard", "-i"],
+    &["xsel", "--clipboard", "--input"],
+];
+
+pub fn get() -> Result<String, String> {
+    for command in PASTE_COMMANDS {
+        let output = Command::new(command[0])
+            .args(&command[1..])
+            .stderr(Stdio::null())
+            .output();
+
+        if let Ok(output) = output {
+            if output.status.success() {
+                return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+            }
+        }
+    }
+
+    Err("Could not read the clipboard, no clipboard tool found".to_string())
+}
+
+pub fn set(text: &str) -> Result<(), String> {
+    for command in COPY_COMMANDS {
+        let child = Command::new(command[0])
+            .args(&command[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+
+        if let Ok(mut child) = child {
+            if let Some(stdin) = child.stdin.as_mut() {
+                // A tool that stopped reading would otherwise be left behind
+                if stdin.write_all(text.as_bytes()).is_err() {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    continue;
+                }
+            }
+            drop(child.stdin.take());
+
+            if matches!(child.wait(), Ok(status) if status.success()) {
+                return Ok(());
+            }
+        }
+    }
+
+    Err("Could not write to the clipboard, no clipboard tool found".to_string())
+}
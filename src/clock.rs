@@ -0,0 +1,295 @@
+use std::fmt;
+
+use ChessAPI::piece::*;
+
+#[derive(Clone)]
+pub struct TimeControlStage {
+    // Number of moves to play in this stage, None means the rest of the game
+    pub moves: Option<u32>,
+    pub time: f64,
+    pub increment: f64,
+    // Bronstein delay instead of a Fischer increment, only the time used on a
+    // move is given back, up to `increment` seconds
+    pub delay: bool,
+}
+
+#[derive(Clone)]
+pub struct TimeControl {
+    pub stages: Vec<TimeControlStage>,
+}
+
+impl TimeControl {
+    // Parses time controls written like the PGN TimeControl tag, in seconds.
+    // Stages are separated by ':' and look like "[moves/]time[+increment]",
+    // a 'd' instead of '+' gives a Bronstein delay. For example "300+2",
+    // "180d2" or "40/5400+30:1800+30".
+    pub fn parse(text: &str) -> Result<TimeControl, String> {
+        let stages = text
+            .split(':')
+            .map(|stage| {
+                let invalid = || format!("Invalid time control stage \"{}\"", stage);
+                // Rust also reads "nan", "inf" and negative numbers as floats
+                let number = |s: &str| {
+                    s.parse::<f64>()
+                        .ok()
+                        .filter(|n| n.is_finite() && *n >= 0.0)
+                        .ok_or_else(invalid)
+                };
+
+                let (moves, rest) = match stage.split_once('/') {
+                    Some((moves, rest)) => {
+                        (Some(moves.parse::<u32>().map_err(|_| invalid())?), rest)
+                    }
+                    None => (None, stage),
+                };
+
+                let (time, increment, delay) = if let Some((time, inc)) = rest.split_once('+') {
+                    (number(time)?, number(inc)?, false)
+                } else if let Some((time, delay)) = rest.split_once('d') {
+                    (number(time)?, number(delay)?, true)
+                } else {
+                    (number(rest)?, 0.0, false)
+                };
+
+                if time <= 0.0 || increment < 0.0 || moves == Some(0) {
+                    return Err(invalid());
+                }
+
+                Ok(TimeControlStage {
+                    moves,
+                    time,
+                    increment,
+                    delay,
+                })
+            })
+            .collect::<Result<Vec<TimeControlStage>, String>>()?;
+
+        Ok(TimeControl { stages })
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let stages = self
+            .stages
+            .iter()
+            .map(|stage| {
+                let mut out = String::new();
+                if let Some(moves) = stage.moves {
+                    out.push_str(&format!("{}/", moves));
+                }
+                out.push_str(&format!("{}", stage.time));
+                if stage.increment > 0.0 {
+                    let separator = if stage.delay { "d" } else { "+" };
+                    out.push_str(&format!("{}{}", separator, stage.increment));
+                }
+                out
+            })
+            .collect::<Vec<String>>();
+
+        write!(f, "{}", stages.join(":"))
+    }
+}
+
+pub fn color_index(color: Color) -> usize {
+    match color {
+        Color::White => 0,
+        Color::Black => 1,
+    }
+}
+
+#[derive(Clone)]
+pub struct Clock {
+    pub time_control: TimeControl,
+    // Indexed by `color_index`
+    pub remaining: [f64; 2],
+    stage: [usize; 2],
+    stage_moves: [u32; 2],
+    // Time used on the move currently being thought about
+    spent: f64,
+    pub running: bool,
+}
+
+impl Clock {
+    pub fn new(time_control: TimeControl) -> Clock {
+        let time = time_control.stages[0].time;
+
+        Clock {
+            time_control,
+            remaining: [time, time],
+            stage: [0, 0],
+            stage_moves: [0, 0],
+            spent: 0.0,
+            running: false,
+        }
+    }
+
+    pub fn remaining(&self, color: Color) -> f64 {
+        self.remaining[color_index(color)]
+    }
+
+    // The increment or delay `color` gets in the stage it is in
+    pub fn increment(&self, color: Color) -> f64 {
+        self.time_control.stages[self.stage[color_index(color)]].increment
+    }
+
+    pub fn is_flagged(&self, color: Color) -> bool {
+        self.remaining(color) <= 0.0
+    }
+
+    pub fn tick(&mut self, color: Color, dt: f64) {
+        if !self.running {
+            return;
+        }
+
+        let remaining = &mut self.remaining[color_index(color)];
+        *remaining = (*remaining - dt).max(0.0);
+        self.spent += dt;
+    }
+
+    // Called after `color` has made a move
+    pub fn move_made(&mut self, color: Color) {
+        let i = color_index(color);
+        let stage = &self.time_control.stages[self.stage[i]];
+
+        self.remaining[i] += if stage.delay {
+            self.spent.min(stage.increment)
+        } else {
+            stage.increment
+        };
+
+        self.stage_moves[i] += 1;
+        if Some(self.stage_moves[i]) == stage.moves {
+            // The last stage repeats if it also has a move count
+            if self.stage[i] + 1 < self.time_control.stages.len() {
+                self.stage[i] += 1;
+            }
+            self.stage_moves[i] = 0;
+            self.remaining[i] += self.time_control.stages[self.stage[i]].time;
+        }
+
+        self.spent = 0.0;
+        self.running = true;
+    }
+}
+
+pub fn format_time(seconds: f64) -> String {
+    if seconds < 10.0 {
+        return format!("{:.1}", seconds.max(0.0));
+    }
+
+    let seconds = seconds.ceil() as u64;
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock_for(text: &str) -> Clock {
+        Clock::new(TimeControl::parse(text).unwrap())
+    }
+
+    #[test]
+    fn time_controls_are_parsed() {
+        let tc = TimeControl::parse("40/5400+30:1800+30").unwrap();
+        assert_eq!(tc.stages.len(), 2);
+        assert_eq!(tc.stages[0].moves, Some(40));
+        assert_eq!((tc.stages[0].time, tc.stages[0].increment), (5400.0, 30.0));
+        assert_eq!(tc.stages[1].moves, None);
+        assert_eq!((tc.stages[1].time, tc.stages[1].increment), (1800.0, 30.0));
+
+        let tc = TimeControl::parse("180d2").unwrap();
+        assert!(tc.stages[0].delay);
+        assert_eq!(tc.stages[0].increment, 2.0);
+
+        for text in ["40/5400+30:1800+30", "300", "300+2", "180d2", "0.5+0.25"] {
+            assert_eq!(TimeControl::parse(text).unwrap().to_string(), text);
+        }
+    }
+
+    #[test]
+    fn bad_time_controls_are_rejected() {
+        for text in [
+            "", "0", "-300", "300+-2", "300d-2", "nan", "inf", "300+inf", "300+NaN", "0/300",
+            "x/300", "300+2:", "5 min",
+        ] {
+            assert!(TimeControl::parse(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn the_clock_starts_with_the_first_move() {
+        let mut clock = clock_for("300+2");
+        clock.tick(Color::White, 10.0);
+        assert_eq!(clock.remaining(Color::White), 300.0);
+
+        clock.move_made(Color::White);
+        assert_eq!(clock.remaining(Color::White), 302.0);
+
+        clock.tick(Color::Black, 10.0);
+        clock.move_made(Color::Black);
+        assert_eq!(clock.remaining(Color::Black), 292.0);
+    }
+
+    #[test]
+    fn delay_gives_back_at_most_the_time_used() {
+        let mut clock = clock_for("60d2");
+        clock.move_made(Color::White);
+        assert_eq!(clock.remaining(Color::White), 60.0);
+
+        clock.tick(Color::Black, 1.5);
+        clock.move_made(Color::Black);
+        assert_eq!(clock.remaining(Color::Black), 60.0);
+
+        clock.tick(Color::White, 5.0);
+        clock.move_made(Color::White);
+        assert_eq!(clock.remaining(Color::White), 57.0);
+    }
+
+    #[test]
+    fn stages_add_their_time() {
+        let mut clock = clock_for("40/5400+30:1800+30");
+        for _ in 0..40 {
+            clock.move_made(Color::White);
+        }
+        assert_eq!(clock.remaining(Color::White), 5400.0 + 40.0 * 30.0 + 1800.0);
+
+        clock.move_made(Color::White);
+        assert_eq!(clock.remaining(Color::White), 8430.0);
+        assert_eq!(clock.increment(Color::White), 30.0);
+        assert_eq!(clock.remaining(Color::Black), 5400.0);
+
+        // The last stage starts over when it has a move count
+        let mut clock = clock_for("2/100");
+        for _ in 0..4 {
+            clock.move_made(Color::Black);
+        }
+        assert_eq!(clock.remaining(Color::Black), 300.0);
+    }
+
+    #[test]
+    fn running_out_of_time() {
+        let mut clock = clock_for("1");
+        clock.move_made(Color::White);
+        clock.tick(Color::Black, 0.6);
+        assert!(!clock.is_flagged(Color::Black));
+
+        clock.tick(Color::Black, 0.6);
+        assert!(clock.is_flagged(Color::Black));
+        assert_eq!(clock.remaining(Color::Black), 0.0);
+    }
+
+    #[test]
+    fn times_are_formatted() {
+        assert_eq!(format_time(-1.0), "0.0");
+        assert_eq!(format_time(5.5), "5.5");
+        assert_eq!(format_time(59.2), "1:00");
+        assert_eq!(format_time(600.0), "10:00");
+        assert_eq!(format_time(3661.0), "1:01:01");
+    }
+}
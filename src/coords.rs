@@ -0,0 +1,83 @@
+// Maps between board coordinates (col, row) and pixels on the screen. Every
+// conversion goes through here so that flipping the board affects drawing,
+// animations and mouse input the same way.
+#[derive(Clone, Copy)]
+pub struct BoardCoords {
+    // Top left corner of the board on the screen
+    pub x: f64,
+    pub y: f64,
+    pub size: f64,
+    // Black at the bottom instead of White
+    pub flipped: bool,
+}
+
+impl BoardCoords {
+    pub fn new(x: f64, y: f64, size: f64, flipped: bool) -> BoardCoords {
+        BoardCoords {
+            x,
+            y,
+            size,
+            flipped,
+        }
+    }
+
+    pub fn square_size(&self) -> f64 {
+        self.size / 8.0
+    }
+
+    pub fn rect(&self) -> [f64; 4] {
+        [self.x, self.y, self.size, self.size]
+    }
+
+    // Screen position of the top left corner of a square, fractional board
+    // coordinates are used for pieces that are moving between squares
+    pub fn to_screen(&self, (col, row): (f64, f64)) -> [f64; 2] {
+        let (col, row) = match self.flipped {
+            true => (7.0 - col, 7.0 - row),
+            false => (col, row),
+        };
+
+        [
+            self.x + col * self.square_size(),
+            self.y + row * self.square_size(),
+        ]
+    }
+
+    // The inverse of `to_screen`
+    pub fn to_board(&self, [x, y]: [f64; 2]) -> (f64, f64) {
+        let col = (x - self.x) / self.square_size();
+        let row = (y - self.y) / self.square_size();
+
+        match self.flipped {
+            true => (7.0 - col, 7.0 - row),
+            false => (col, row),
+        }
+    }
+
+    pub fn square_at(&self, [x, y]: [f64; 2]) -> Option<(usize, usize)> {
+        let col = ((x - self.x) / self.square_size()).floor();
+        let row = ((y - self.y) / self.square_size()).floor();
+
+        if col < 0.0 || col >= 8.0 || row < 0.0 || row >= 8.0 {
+            return None;
+        }
+
+        let (col, row) = (col as usize, row as usize);
+        Some(match self.flipped {
+            true => (7 - col, 7 - row),
+            false => (col, row),
+        })
+    }
+
+    // Top left corner of option `i` in the promotion dialog, which is
+    // centered on the board
+    pub fn promotion_option(&self, i: usize) -> [f64; 2] {
+        let size = self.square_size();
+        let gap = 10.0;
+
+        [
+            self.x + self.size / 2.0 - (size + gap) * 2.0 + gap / 2.0 + (size + gap) * i as f64,
+            self.y + self.size / 2.0 - size / 2.0,
+        ]
+    }
+}
@@ -0,0 +1,97 @@
+use piston::input::*;
+use piston::Event;
+
+use ChessAPI::piece::*;
+
+use crate::animation::{AnimateValue, Animation, AnimationTimingFunction};
+use crate::fen;
+use crate::layout;
+
+// Questions that need a yes or no before anything else can happen
+#[derive(Clone, Copy, PartialEq)]
+pub enum Dialog {
+    // Does this side really want to resign
+    Resign(Color),
+    OfferDraw(Color),
+    // This side offered a draw to a human opponent
+    DrawOffered(Color),
+    // Throws the game away and starts over
+    NewGame,
+}
+
+impl Dialog {
+    pub fn text(&self) -> String {
+        match self {
+            Dialog::Resign(color) => format!("{} resigns?", fen::color_name(*color)),
+            Dialog::OfferDraw(color) => format!("{} offers a draw?", fen::color_name(*color)),
+            Dialog::DrawOffered(color) => format!("{} offers a draw", fen::color_name(*color)),
+            Dialog::NewGame => "Start a new game?".to_string(),
+        }
+    }
+
+    pub fn buttons(&self) -> [&'static str; 2] {
+        match self {
+            Dialog::Resign(_) => ["Resign", "Cancel"],
+            Dialog::OfferDraw(_) => ["Offer", "Cancel"],
+            Dialog::DrawOffered(_) => ["Accept", "Decline"],
+            Dialog::NewGame => ["New game", "Cancel"],
+        }
+    }
+}
+
+// The question on screen, the owner decides what the answer does
+pub struct ConfirmDialog {
+    // Kept while the dialog fades out
+    pub dialog: Option<Dialog>,
+    pub open: bool,
+    pub animation: AnimateValue,
+}
+
+impl ConfirmDialog {
+    pub fn new() -> ConfirmDialog {
+        ConfirmDialog {
+            dialog: None,
+            open: false,
+            animation: AnimateValue::new()
+                .duration(0.1)
+                .timing_function(AnimationTimingFunction::Ease)
+                .finish(),
+        }
+    }
+
+    pub fn open(&mut self, dialog: Dialog) {
+        self.dialog = Some(dialog);
+        self.open = true;
+        self.animation.reset();
+    }
+
+    // Returns the question if it was still open
+    pub fn close(&mut self) -> Option<Dialog> {
+        if !self.open {
+            return None;
+        }
+
+        self.open = false;
+        self.animation.reset();
+        self.dialog
+    }
+
+    // Return and the first button answer yes, Escape and the second button
+    // answer no
+    pub fn answer(&self, e: &Event, buttons: [[f64; 4]; 2], cursor: [f64; 2]) -> Option<bool> {
+        match e.press_args() {
+            Some(Button::Keyboard(Key::Return)) => Some(true),
+            Some(Button::Keyboard(Key::Escape)) => Some(false),
+            Some(Button::Mouse(MouseButton::Left)) => {
+                if layout::contains(buttons[0], cursor) {
+                    Some(true)
+                } else if layout::contains(buttons[1], cursor) {
+                    Some(false)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
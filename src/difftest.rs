@@ -0,0 +1,285 @@
+use std::path::Path;
+
+use ChessAPI::board::*;
+use ChessAPI::piece::*;
+
+use crate::ai::Random;
+use crate::backend::{BackendKind, Board, ChessBackend};
+use crate::fen::Fen;
+use crate::pgn;
+use crate::uci;
+
+// Random games are cut off here, most of them would otherwise shuffle pieces
+// around for a very long time
+const MAX_PLIES: usize = 300;
+
+// The first position where the two libraries disagree
+pub struct Divergence {
+    pub game: String,
+    pub ply: usize,
+    pub fen: String,
+    // The move that led to the position, in long algebraic notation
+    pub last_move: Option<String>,
+    pub differences: Vec<String>,
+}
+
+impl Divergence {
+    pub fn report(&self) -> String {
+        let mut report = format!("{}, ply {}: {}", self.game, self.ply, self.fen);
+        if let Some(last_move) = &self.last_move {
+            report.push_str(&format!("\n  after {}", last_move));
+        }
+        for difference in &self.differences {
+            report.push_str(&format!("\n  {}", difference));
+        }
+        report
+    }
+}
+
+fn legal_moves(board: &dyn ChessBackend) -> Vec<String> {
+    let mut moves = board
+        .generate_legal_moves()
+        .iter()
+        .map(uci::move_to_uci)
+        .collect::<Vec<String>>();
+    moves.sort();
+    moves.dedup();
+    moves
+}
+
+// The placement, side to move and castling fields. En passant squares are
+// left out since libraries disagree on whether to set them when no capture
+// is possible.
+fn position(board: &dyn ChessBackend) -> String {
+    board
+        .fen()
+        .split_whitespace()
+        .take(3)
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+fn compare(boards: &[Board; 2]) -> Vec<String> {
+    let names = BackendKind::ALL.map(|kind| kind.name());
+    let mut differences = Vec::new();
+
+    let moves = [legal_moves(&boards[0]), legal_moves(&boards[1])];
+    for (i, j) in [(0, 1), (1, 0)] {
+        let only = moves[i]
+            .iter()
+            .filter(|mv| !moves[j].contains(mv))
+            .cloned()
+            .collect::<Vec<String>>();
+
+        if !only.is_empty() {
+            differences.push(format!("moves only {} allows: {}", names[i], only.join(" ")));
+        }
+    }
+
+    let status = |board: &Board| [board.is_check(), board.is_checkmate(), board.is_stalemate()];
+    let statuses = [status(&boards[0]), status(&boards[1])];
+    for (k, name) in ["check", "checkmate", "stalemate"].iter().enumerate() {
+        if statuses[0][k] != statuses[1][k] {
+            differences.push(format!(
+                "{}: {} says {}, {} says {}",
+                name, names[0], statuses[0][k], names[1], statuses[1][k]
+            ));
+        }
+    }
+
+    let positions = [position(&boards[0]), position(&boards[1])];
+    if positions[0] != positions[1] {
+        differences.push(format!(
+            "positions: {} has {}, {} has {}",
+            names[0], positions[0], names[1], positions[1]
+        ));
+    }
+
+    differences
+}
+
+// Plays the moves picked by `next_move` on both libraries at once until one
+// of them diverges or `next_move` runs out of moves
+fn play_in_lockstep(
+    game: String,
+    start: &Fen,
+    mut next_move: impl FnMut(usize, &dyn ChessBackend) -> Option<Move>,
+) -> Result<Option<Divergence>, String> {
+    let mut boards = [
+        BackendKind::ChessApi.board_from_fen(start)?,
+        BackendKind::Fritiofr.board_from_fen(start)?,
+    ];
+    let mut last_move = None;
+
+    for ply in 0.. {
+        let differences = compare(&boards);
+        if !differences.is_empty() {
+            return Ok(Some(Divergence {
+                game,
+                ply,
+                fen: boards[0].fen(),
+                last_move,
+                differences,
+            }));
+        }
+
+        let mv = match next_move(ply, boards[0].as_ref()) {
+            Some(mv) => mv,
+            None => return Ok(None),
+        };
+
+        let fen = boards[0].fen();
+        let rejected = boards
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, board)| {
+                board.make_move(&mv).err().map(|err| {
+                    let name = BackendKind::ALL[i].name();
+                    format!("{} rejected {}: {}", name, uci::move_to_uci(&mv), err)
+                })
+            })
+            .collect::<Vec<String>>();
+
+        if !rejected.is_empty() {
+            return Ok(Some(Divergence {
+                game,
+                ply,
+                fen,
+                last_move,
+                differences: rejected,
+            }));
+        }
+
+        last_move = Some(uci::move_to_uci(&mv));
+    }
+
+    Ok(None)
+}
+
+fn random_move(random: &mut Random, board: &dyn ChessBackend) -> Option<Move> {
+    let moves = board.generate_legal_moves();
+    if moves.is_empty() {
+        return None;
+    }
+
+    let mut mv = moves[random.below(moves.len())].clone();
+
+    let pawn = board.get_board()[mv.from.row as usize][mv.from.col as usize]
+        .map(|piece| piece.piece_type)
+        == Some(PieceType::Pawn);
+
+    if pawn && mv.promotion.is_none() && (mv.to.row == 0 || mv.to.row == 7) {
+        let pieces = [
+            PieceType::Queen,
+            PieceType::Rook,
+            PieceType::Bishop,
+            PieceType::Knight,
+        ];
+        mv.promotion = Some(pieces[random.below(pieces.len())]);
+    }
+
+    Some(mv)
+}
+
+// Plays `games` random games from `start` and every game in `pgn` through both
+// libraries, printing the first divergence of each game
+pub fn run(games: u32, start: &Fen, pgn: Option<&Path>, seed: u64) -> Result<(), String> {
+    let mut random = Random(seed.max(1));
+    let mut divergences = 0;
+    let mut played = 0;
+
+    println!("Random games with seed {}", seed);
+
+    for i in 0..games {
+        let name = format!("Random game {}", i + 1);
+        let divergence = play_in_lockstep(name, start, |ply, board| {
+            if ply >= MAX_PLIES {
+                return None;
+            }
+            random_move(&mut random, board)
+        })?;
+
+        played += 1;
+        if let Some(divergence) = divergence {
+            divergences += 1;
+            println!("{}", divergence.report());
+        }
+    }
+
+    if let Some(path) = pgn {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+
+        for (i, game) in pgn::parse_pgn(&text)
+            .map_err(|err| err.to_string())?
+            .iter()
+            .enumerate()
+        {
+            let moves = match game.legal_moves(i, BackendKind::ChessApi) {
+                Ok(moves) => moves,
+                Err(err) => {
+                    println!("Skipping game {}: {}", i + 1, err);
+                    continue;
+                }
+            };
+
+            let start = match game.tag("FEN") {
+                Some(fen) => Fen::parse(fen).map_err(|err| err.to_string())?,
+                None => Fen::start(),
+            };
+
+            let name = format!("Game {} {}", i + 1, game.title());
+            let divergence = play_in_lockstep(name, &start, |ply, _| moves.get(ply).cloned())?;
+
+            played += 1;
+            if let Some(divergence) = divergence {
+                divergences += 1;
+                println!("{}", divergence.report());
+            }
+        }
+    }
+
+    println!();
+    println!("{} of {} games diverged", divergences, played);
+
+    if divergences > 0 {
+        return Err(format!("The libraries disagree in {} games", divergences));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backends_agree_on_random_games() {
+        // The second start reaches castling and promotions early
+        let starts = [
+            Fen::start(),
+            Fen::parse("r3k2r/1P4P1/8/8/8/8/1p4p1/R3K2R w KQkq - 0 1")
+                .unwrap_or_else(|err| panic!("{}", err)),
+        ];
+
+        let mut random = Random(0x5eed);
+        let mut plies = 0;
+        let mut games = 0;
+
+        while plies < 400 {
+            let name = format!("Random game {}", games + 1);
+            let start = &starts[games % starts.len()];
+            let divergence = play_in_lockstep(name, start, |ply, board| {
+                let mv = random_move(&mut random, board).filter(|_| ply < 100);
+                plies += mv.is_some() as usize;
+                mv
+            })
+            .unwrap();
+
+            if let Some(divergence) = divergence {
+                panic!("{}", divergence.report());
+            }
+            games += 1;
+        }
+    }
+}
@@ -0,0 +1,494 @@
+use std::fmt;
+
+use ChessAPI::board::*;
+use ChessAPI::piece::*;
+
+use crate::backend::BackendKind;
+use crate::backend::Board;
+use crate::san;
+
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+pub struct FenError {
+    pub field: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid FEN ({}): {}", self.field, self.message)
+    }
+}
+
+fn error(field: &'static str, message: String) -> FenError {
+    FenError { field, message }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Castling {
+    pub white_king: bool,
+    pub white_queen: bool,
+    pub black_king: bool,
+    pub black_queen: bool,
+}
+
+// Everything needed to describe a position, the board library only keeps
+// track of the pieces and the side to move so the rest is tracked here
+#[derive(Clone)]
+pub struct Fen {
+    pub pieces: [[Option<Piece>; 8]; 8],
+    pub turn: Color,
+    pub castling: Castling,
+    // (col, row) of the square a pawn skipped over on the last move
+    pub en_passant: Option<(usize, usize)>,
+    pub halfmove: u32,
+    pub fullmove: u32,
+}
+
+impl Fen {
+    pub fn start() -> Fen {
+        match Fen::parse(START_FEN) {
+            Ok(fen) => fen,
+            Err(_) => unreachable!(),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Fen, FenError> {
+        let fields = text.split_whitespace().collect::<Vec<&str>>();
+
+        // The clocks are often left out, so only the first four fields are required
+        if fields.len() != 4 && fields.len() != 6 {
+            return Err(error(
+                "fields",
+                format!("expected 4 or 6 fields, found {}", fields.len()),
+            ));
+        }
+
+        let mut pieces = [[None; 8]; 8];
+        let ranks = fields[0].split('/').collect::<Vec<&str>>();
+        if ranks.len() != 8 {
+            return Err(error("placement", format!("expected 8 ranks, found {}", ranks.len())));
+        }
+
+        for (row, rank) in ranks.iter().enumerate() {
+            let mut col = 0;
+            for c in rank.chars() {
+                if let Some(empty) = c.to_digit(10).filter(|d| (1..=8).contains(d)) {
+                    col += empty as usize;
+                    continue;
+                }
+
+                let piece_type = san::char_piece(c).ok_or_else(|| {
+                    error("placement", format!("unknown piece '{}' on rank {}", c, 8 - row))
+                })?;
+
+                if col < 8 {
+                    pieces[row][col] = Some(Piece {
+                        color: if c.is_ascii_uppercase() {
+                            Color::White
+                        } else {
+                            Color::Black
+                        },
+                        piece_type,
+                    });
+                }
+                col += 1;
+            }
+
+            if col != 8 {
+                return Err(error(
+                    "placement",
+                    format!("rank {} has {} squares instead of 8", 8 - row, col),
+                ));
+            }
+        }
+
+        for color in [Color::White, Color::Black] {
+            let kings = pieces
+                .iter()
+                .flatten()
+                .filter(|p| is_piece(**p, color, PieceType::King))
+                .count();
+
+            // Kingless positions are allowed for testing, more than one king is not
+            if kings > 1 {
+                return Err(error(
+                    "placement",
+                    format!("{} has {} kings", color_name(color), kings),
+                ));
+            }
+        }
+
+        for row in [0, 7] {
+            if pieces[row]
+                .iter()
+                .any(|p| p.map(|p| p.piece_type) == Some(PieceType::Pawn))
+            {
+                return Err(error("placement", format!("pawn on rank {}", 8 - row)));
+            }
+        }
+
+        let turn = match fields[1] {
+            "w" => Color::White,
+            "b" => Color::Black,
+            other => {
+                return Err(error(
+                    "side to move",
+                    format!("expected 'w' or 'b', found '{}'", other),
+                ))
+            }
+        };
+
+        let mut castling = Castling {
+            white_king: false,
+            white_queen: false,
+            black_king: false,
+            black_queen: false,
+        };
+
+        if fields[2] != "-" {
+            for c in fields[2].chars() {
+                let (right, color, row, rook_col) = match c {
+                    'K' => (&mut castling.white_king, Color::White, 7, 7),
+                    'Q' => (&mut castling.white_queen, Color::White, 7, 0),
+                    'k' => (&mut castling.black_king, Color::Black, 0, 7),
+                    'q' => (&mut castling.black_queen, Color::Black, 0, 0),
+                    _ => {
+                        return Err(error("castling", format!("unknown castling right '{}'", c)))
+                    }
+                };
+
+                if !is_piece(pieces[row][4], color, PieceType::King)
+                    || !is_piece(pieces[row][rook_col], color, PieceType::Rook)
+                {
+                    return Err(error(
+                        "castling",
+                        format!("'{}' needs the king and rook on their starting squares", c),
+                    ));
+                }
+
+                *right = true;
+            }
+        }
+
+        let en_passant = match fields[3] {
+            "-" => None,
+            square => {
+                let chars = square.chars().collect::<Vec<char>>();
+                let expected_rank = if turn == Color::White { '6' } else { '3' };
+
+                if chars.len() != 2 || !('a'..='h').contains(&chars[0]) || chars[1] != expected_rank
+                {
+                    return Err(error(
+                        "en passant",
+                        format!("'{}' is not a square on rank {}", square, expected_rank),
+                    ));
+                }
+
+                let col = chars[0] as usize - 'a' as usize;
+                let row = if turn == Color::White { 2 } else { 5 };
+                Some((col, row))
+            }
+        };
+
+        let clock = |i: usize, name: &'static str| -> Result<u32, FenError> {
+            match fields.get(i) {
+                Some(value) => value
+                    .parse()
+                    .map_err(|_| error(name, format!("'{}' is not a number", value))),
+                None => Ok(if i == 4 { 0 } else { 1 }),
+            }
+        };
+
+        let halfmove = clock(4, "halfmove clock")?;
+        let fullmove = clock(5, "fullmove number")?.max(1);
+
+        Ok(Fen {
+            pieces,
+            turn,
+            castling,
+            en_passant,
+            halfmove,
+            fullmove,
+        })
+    }
+
+    pub fn to_board(&self, kind: BackendKind) -> Result<Board, FenError> {
+        kind.board_from_fen(self).map_err(|message| error("position", message))
+    }
+
+    // The position after `mv`, `pieces` are the pieces after the move was made
+    pub fn after_move(&self, mv: &Move, pieces: [[Option<Piece>; 8]; 8]) -> Fen {
+        let moved = self.pieces[mv.from.row as usize][mv.from.col as usize];
+        let pawn_move = moved.map(|p| p.piece_type) == Some(PieceType::Pawn);
+        let capture = self.pieces[mv.to.row as usize][mv.to.col as usize].is_some()
+            || (pawn_move && mv.from.col != mv.to.col);
+
+        let mut castling = self.castling;
+        for (col, row) in [(mv.from.col, mv.from.row), (mv.to.col, mv.to.row)] {
+            match (col, row) {
+                (4, 7) => {
+                    castling.white_king = false;
+                    castling.white_queen = false;
+                }
+                (4, 0) => {
+                    castling.black_king = false;
+                    castling.black_queen = false;
+                }
+                (7, 7) => castling.white_king = false,
+                (0, 7) => castling.white_queen = false,
+                (7, 0) => castling.black_king = false,
+                (0, 0) => castling.black_queen = false,
+                _ => {}
+            }
+        }
+
+        let en_passant = if pawn_move && (mv.from.row - mv.to.row).abs() == 2 {
+            Some((mv.from.col as usize, ((mv.from.row + mv.to.row) / 2) as usize))
+        } else {
+            None
+        };
+
+        Fen {
+            pieces,
+            turn: match self.turn {
+                Color::White => Color::Black,
+                Color::Black => Color::White,
+            },
+            castling,
+            en_passant,
+            halfmove: if pawn_move || capture {
+                0
+            } else {
+                self.halfmove + 1
+            },
+            fullmove: if self.turn == Color::Black {
+                self.fullmove + 1
+            } else {
+                self.fullmove
+            },
+        }
+    }
+}
+
+impl fmt::Display for Fen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ranks = self
+            .pieces
+            .iter()
+            .map(|rank| {
+                let mut out = String::new();
+                let mut empty = 0;
+                for square in rank {
+                    match square {
+                        Some(piece) => {
+                            if empty > 0 {
+                                out.push_str(&empty.to_string());
+                                empty = 0;
+                            }
+                            let c = san::piece_char(piece.piece_type);
+                            out.push(if piece.color == Color::White {
+                                c
+                            } else {
+                                c.to_ascii_lowercase()
+                            });
+                        }
+                        None => empty += 1,
+                    }
+                }
+                if empty > 0 {
+                    out.push_str(&empty.to_string());
+                }
+                out
+            })
+            .collect::<Vec<String>>();
+
+        let mut castling = String::new();
+        for (right, c) in [
+            (self.castling.white_king, 'K'),
+            (self.castling.white_queen, 'Q'),
+            (self.castling.black_king, 'k'),
+            (self.castling.black_queen, 'q'),
+        ] {
+            if right {
+                castling.push(c);
+            }
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+
+        let en_passant = match self.en_passant {
+            Some((col, row)) => {
+                format!("{}{}", san::file_char(col as i8), san::rank_char(row as i8))
+            }
+            None => "-".to_string(),
+        };
+
+        write!(
+            f,
+            "{} {} {} {} {} {}",
+            ranks.join("/"),
+            if self.turn == Color::White { "w" } else { "b" },
+            castling,
+            en_passant,
+            self.halfmove,
+            self.fullmove
+        )
+    }
+}
+
+pub fn is_piece(square: Option<Piece>, color: Color, piece_type: PieceType) -> bool {
+    match square {
+        Some(piece) => piece.color == color && piece.piece_type == piece_type,
+        None => false,
+    }
+}
+
+pub fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::White => "White",
+        Color::Black => "Black",
+    }
+}
+
+pub fn opposite(color: Color) -> Color {
+    match color {
+        Color::White => Color::Black,
+        Color::Black => Color::White,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Fen {
+        Fen::parse(text).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    // The field `text` is rejected for
+    fn rejected_field(text: &str) -> &'static str {
+        match Fen::parse(text) {
+            Ok(_) => panic!("{} was accepted", text),
+            Err(err) => err.field,
+        }
+    }
+
+    fn square(col: i8, row: i8) -> Position {
+        Position { col, row }
+    }
+
+    #[test]
+    fn start_position() {
+        let fen = Fen::start();
+        assert_eq!(fen.to_string(), START_FEN);
+        assert!(fen.turn == Color::White);
+        assert!(fen.en_passant.is_none());
+        assert_eq!((fen.halfmove, fen.fullmove), (0, 1));
+        assert!(is_piece(fen.pieces[7][4], Color::White, PieceType::King));
+        assert!(is_piece(fen.pieces[0][3], Color::Black, PieceType::Queen));
+    }
+
+    #[test]
+    fn positions_round_trip() {
+        for text in [
+            START_FEN,
+            "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w Kq d6 0 3",
+            "rnbqkbnr/pppp1ppp/8/8/3Pp3/8/PPP1PPPP/RNBQKBNR b kq d3 0 2",
+            "8/8/8/4k3/8/8/8/4K3 b - - 49 120",
+            "8/8/8/8/8/8/8/8 w - - 0 1",
+        ] {
+            assert_eq!(parse(text).to_string(), text);
+        }
+
+        // The clocks may be left out
+        assert_eq!(
+            parse("4k3/8/8/8/8/8/8/4K3 w - -").to_string(),
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1"
+        );
+    }
+
+    #[test]
+    fn castling_and_en_passant_fields() {
+        let fen = parse("rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w Kq d6 0 3");
+        assert!(fen.castling.white_king && fen.castling.black_queen);
+        assert!(!fen.castling.white_queen && !fen.castling.black_king);
+        assert_eq!(fen.en_passant, Some((3, 2)));
+
+        let fen = parse("rnbqkbnr/pppp1ppp/8/8/3Pp3/8/PPP1PPPP/RNBQKBNR b - d3 0 2");
+        assert_eq!(fen.en_passant, Some((3, 5)));
+    }
+
+    #[test]
+    fn moves_update_every_field() {
+        let fen = Fen::start();
+        let mut pieces = fen.pieces;
+        pieces[4][4] = pieces[6][4].take();
+
+        let mv = Move {
+            from: square(4, 6),
+            to: square(4, 4),
+            promotion: None,
+        };
+        let after = fen.after_move(&mv, pieces);
+        assert_eq!(
+            after.to_string(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+
+        let fen = parse("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 5 20");
+        let mut pieces = fen.pieces;
+        pieces[7][0] = pieces[0][0].take();
+
+        let mv = Move {
+            from: square(0, 0),
+            to: square(0, 7),
+            promotion: None,
+        };
+        let after = fen.after_move(&mv, pieces);
+        assert_eq!(after.to_string(), "4k2r/8/8/8/8/8/8/r3K2R w Kk - 0 21");
+    }
+
+    #[test]
+    fn bad_piece_placement_is_rejected() {
+        for text in [
+            "8/8/8/8/8/8/8 w - - 0 1",
+            "8/8/8/8/8/8/8/8/8 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K2 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K4 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K2X w - - 0 1",
+            "4k3/8/8/8/8/8/8/0000K3 w - - 0 1",
+            "4k2k/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/K3K3 w - - 0 1",
+            "P3k3/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/p3K3 w - - 0 1",
+        ] {
+            assert_eq!(rejected_field(text), "placement", "{}", text);
+        }
+    }
+
+    #[test]
+    fn malformed_fields_are_rejected() {
+        for (text, field) in [
+            ("", "fields"),
+            ("4k3/8/8/8/8/8/8/4K3 w", "fields"),
+            ("4k3/8/8/8/8/8/8/4K3 w - - 0", "fields"),
+            ("4k3/8/8/8/8/8/8/4K3 w - - 0 1 extra", "fields"),
+            ("4k3/8/8/8/8/8/8/4K3 x - - 0 1", "side to move"),
+            ("4k3/8/8/8/8/8/8/4K3 w X - 0 1", "castling"),
+            ("4k3/8/8/8/8/8/8/4K3 w K - 0 1", "castling"),
+            ("r3k2r/8/8/8/8/8/8/R2K3R w Q - 0 1", "castling"),
+            ("4k3/8/8/8/8/8/8/4K3 w - e3 0 1", "en passant"),
+            ("4k3/8/8/8/8/8/8/4K3 b - e6 0 1", "en passant"),
+            ("4k3/8/8/8/8/8/8/4K3 w - i6 0 1", "en passant"),
+            ("4k3/8/8/8/8/8/8/4K3 w - - x 1", "halfmove clock"),
+            ("4k3/8/8/8/8/8/8/4K3 w - - 0 -1", "fullmove number"),
+        ] {
+            assert_eq!(rejected_field(text), field, "{}", text);
+        }
+
+        let err = Fen::parse("4k3/8/8/8/8/8/8/4K3 w").err().unwrap();
+        assert_eq!(err.to_string(), "Invalid FEN (fields): expected 4 or 6 fields, found 2");
+    }
+}
@@ -0,0 +1,68 @@
+use std::path::Path;
+
+use piston::input::*;
+
+use crate::animation::{AnimateValue, Animation, AnimationTimingFunction};
+use crate::pgn;
+use crate::pgn::PgnGame;
+
+// The games of the file given with --load-pgn, a list to pick one from is
+// shown when there is more than one
+pub struct GamePicker {
+    pub games: Vec<PgnGame>,
+    pub open: bool,
+    pub selected: usize,
+    pub animation: AnimateValue,
+}
+
+impl GamePicker {
+    pub fn new() -> GamePicker {
+        GamePicker {
+            games: Vec::new(),
+            open: false,
+            selected: 0,
+            animation: AnimateValue::new()
+                .duration(0.1)
+                .timing_function(AnimationTimingFunction::Ease)
+                .finish(),
+        }
+    }
+
+    // Replaces the games with the ones in `path`, starting at the first
+    pub fn read(&mut self, path: &Path) -> Result<(), String> {
+        self.games = pgn::read_pgn_file(path)?;
+        self.selected = 0;
+        Ok(())
+    }
+
+    pub fn open(&mut self) {
+        self.open = true;
+        self.animation.reset();
+    }
+
+    pub fn close(&mut self) {
+        self.open = false;
+        self.animation.reset();
+    }
+
+    // Up and Down move through the list, Return picks a game and Escape
+    // closes the list. Returns the index of the game picked
+    pub fn key(&mut self, key: Key) -> Option<usize> {
+        match key {
+            Key::Up => self.selected = self.selected.saturating_sub(1),
+            Key::Down => {
+                if self.selected + 1 < self.games.len() {
+                    self.selected += 1;
+                }
+            }
+            Key::Return => {
+                self.close();
+                return Some(self.selected);
+            }
+            Key::Escape => self.close(),
+            _ => {}
+        }
+
+        None
+    }
+}
@@ -0,0 +1,143 @@
+use ChessAPI::piece::*;
+
+use crate::fen::color_name;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Termination {
+    Checkmate,
+    Stalemate,
+    Resignation,
+    // A draw when the side with time left could never checkmate
+    Timeout,
+    Agreement,
+    // Claimed by the player to move
+    Repetition,
+    FiftyMoves,
+    // Drawn automatically
+    FivefoldRepetition,
+    SeventyFiveMoves,
+    InsufficientMaterial,
+    // The opponent left and did not come back
+    Abandonment,
+    // Declared drawn by a match runner after too many moves
+    Adjudication,
+}
+
+impl Termination {
+    pub const ALL: [Termination; 12] = [
+        Termination::Checkmate,
+        Termination::Stalemate,
+        Termination::Resignation,
+        Termination::Timeout,
+        Termination::Agreement,
+        Termination::Repetition,
+        Termination::FiftyMoves,
+        Termination::FivefoldRepetition,
+        Termination::SeventyFiveMoves,
+        Termination::InsufficientMaterial,
+        Termination::Abandonment,
+        Termination::Adjudication,
+    ];
+
+    // A single word for sending results over the network
+    pub fn word(&self) -> &'static str {
+        match self {
+            Termination::Checkmate => "checkmate",
+            Termination::Stalemate => "stalemate",
+            Termination::Resignation => "resignation",
+            Termination::Timeout => "timeout",
+            Termination::Agreement => "agreement",
+            Termination::Repetition => "repetition",
+            Termination::FiftyMoves => "fifty-moves",
+            Termination::FivefoldRepetition => "fivefold-repetition",
+            Termination::SeventyFiveMoves => "seventy-five-moves",
+            Termination::InsufficientMaterial => "insufficient-material",
+            Termination::Abandonment => "abandonment",
+            Termination::Adjudication => "adjudication",
+        }
+    }
+
+    pub fn from_word(word: &str) -> Option<Termination> {
+        Termination::ALL
+            .into_iter()
+            .find(|termination| termination.word() == word)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct GameResult {
+    // None for a draw
+    pub winner: Option<Color>,
+    pub termination: Termination,
+}
+
+impl GameResult {
+    pub fn win(winner: Color, termination: Termination) -> GameResult {
+        GameResult {
+            winner: Some(winner),
+            termination,
+        }
+    }
+
+    pub fn draw(termination: Termination) -> GameResult {
+        GameResult {
+            winner: None,
+            termination,
+        }
+    }
+
+    // Parses a PGN score like "1-0"
+    pub fn from_score(score: &str, termination: Termination) -> Option<GameResult> {
+        match score {
+            "1-0" => Some(GameResult::win(Color::White, termination)),
+            "0-1" => Some(GameResult::win(Color::Black, termination)),
+            "1/2-1/2" => Some(GameResult::draw(termination)),
+            _ => None,
+        }
+    }
+
+    // The result as written in PGN
+    pub fn score(&self) -> &'static str {
+        match self.winner {
+            Some(Color::White) => "1-0",
+            Some(Color::Black) => "0-1",
+            None => "1/2-1/2",
+        }
+    }
+
+    pub fn title(&self) -> String {
+        match (self.winner, self.termination) {
+            (Some(winner), _) => format!("{} won", color_name(winner)),
+            (None, Termination::Stalemate) => "Stalemate".to_string(),
+            (None, _) => "Draw".to_string(),
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match (self.termination, self.winner) {
+            (Termination::Checkmate, _) => "by checkmate",
+            (Termination::Stalemate, _) => "no legal moves",
+            (Termination::Resignation, _) => "by resignation",
+            (Termination::Timeout, Some(_)) => "on time",
+            (Termination::Timeout, None) => "timeout vs insufficient material",
+            (Termination::Agreement, _) => "by agreement",
+            (Termination::Repetition, _) => "by threefold repetition",
+            (Termination::FiftyMoves, _) => "by the fifty-move rule",
+            (Termination::FivefoldRepetition, _) => "by fivefold repetition",
+            (Termination::SeventyFiveMoves, _) => "by the seventy-five-move rule",
+            (Termination::InsufficientMaterial, _) => "insufficient material",
+            (Termination::Abandonment, _) => "by abandonment",
+            (Termination::Adjudication, _) => "by adjudication",
+        }
+    }
+
+    // The PGN Termination tag
+    pub fn pgn_termination(&self) -> &'static str {
+        match self.termination {
+            Termination::Timeout => "time forfeit",
+            Termination::Abandonment => "abandoned",
+            Termination::Adjudication => "adjudication",
+            _ => "normal",
+        }
+    }
+}
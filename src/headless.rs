@@ -0,0 +1,166 @@
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use piston::input::UpdateArgs;
+
+use ChessAPI::board::*;
+
+use crate::ai;
+use crate::backend::ChessBackend;
+use crate::chess_controller::ChessController;
+use crate::difftest;
+use crate::fen::Fen;
+use crate::match_runner::MatchRunner;
+use crate::options::{HeadlessTask, Options};
+use crate::uci;
+
+// Runs a task without opening a window and returns the exit code
+pub fn run(task: HeadlessTask, options: Options) -> i32 {
+    let result = match task {
+        HeadlessTask::Perft(depth) => perft_divide(options, depth),
+        HeadlessTask::ValidatePgn(path) => validate_pgn(options, &path),
+        HeadlessTask::Match(games) => run_match(options, games),
+        HeadlessTask::DiffTest(games) => diff_test(options, games),
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("{}", err);
+            1
+        }
+    }
+}
+
+pub fn perft(board: &dyn ChessBackend, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let moves = ai::expand_promotions(board, board.generate_legal_moves());
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+
+    moves
+        .iter()
+        .map(|mv| {
+            let mut child = board.box_clone();
+            match child.make_move(mv) {
+                Ok(_) => perft(&child, depth - 1),
+                Err(_) => 0,
+            }
+        })
+        .sum()
+}
+
+// Prints the node count below every move, like `go perft` in most engines
+fn perft_divide(options: Options, depth: u32) -> Result<(), String> {
+    let mut controller = ChessController::new(options)?;
+    if let Some(err) = controller.error.take() {
+        return Err(err);
+    }
+
+    let start = Instant::now();
+    let board = &controller.board;
+
+    // At depth 0 the position itself is the only node, there are no moves to
+    // divide it by
+    let (moves, mut nodes) = match depth {
+        0 => (Vec::new(), perft(board, 0)),
+        _ => (ai::expand_promotions(board, board.generate_legal_moves()), 0),
+    };
+
+    for mv in moves {
+        let mut child = board.box_clone();
+        if child.make_move(&mv).is_err() {
+            return Err(format!("The board rejected its own move {}", uci::move_to_uci(&mv)));
+        }
+
+        let count = perft(&child, depth - 1);
+        println!("{}: {}", uci::move_to_uci(&mv), count);
+        nodes += count;
+    }
+
+    let seconds = start.elapsed().as_secs_f64();
+    println!();
+    println!("Nodes searched: {}", nodes);
+    println!("Time: {:.3}s ({:.0} nodes/s)", seconds, nodes as f64 / seconds.max(0.001));
+
+    Ok(())
+}
+
+// Loads every game the same way the game picker does and reports the ones
+// that can not be replayed
+fn validate_pgn(options: Options, path: &Path) -> Result<(), String> {
+    let mut controller = ChessController::new(options)?;
+    controller.game_picker.read(path)?;
+    let count = controller.game_picker.games.len();
+    let mut invalid = 0;
+
+    for i in 0..count {
+        let title = controller.game_picker.games[i].title();
+        controller.load_game(i);
+
+        match controller.error.take() {
+            Some(err) => {
+                invalid += 1;
+                println!("Game {} {}: {}", i + 1, title, err);
+            }
+            None => println!("Game {} {}: ok, {} plies", i + 1, title, controller.redo_stack.len()),
+        }
+    }
+
+    println!();
+    println!("{} of {} games are valid", count - invalid, count);
+
+    if invalid > 0 {
+        return Err(format!("{} invalid games in {}", invalid, path.display()));
+    }
+
+    Ok(())
+}
+
+fn run_match(options: Options, games: u32) -> Result<(), String> {
+    let mut runner = MatchRunner::new(options.clone(), games)?;
+    runner.pause_between_games = 0.0;
+
+    let mut controller = ChessController::new(options)?;
+    runner.start_game(&mut controller);
+
+    // Real time passes so that clocks work the same as in the window
+    let mut last = Instant::now();
+    while !runner.is_done() {
+        thread::sleep(Duration::from_millis(10));
+
+        let now = Instant::now();
+        let dt = (now - last).as_secs_f64();
+        last = now;
+
+        controller.update(&UpdateArgs { dt });
+        runner.update(&mut controller, dt);
+
+        if let Some(err) = controller.error.take() {
+            return Err(err);
+        }
+    }
+
+    Ok(())
+}
+
+fn diff_test(options: Options, games: u32) -> Result<(), String> {
+    let start = match &options.fen {
+        Some(fen) => Fen::parse(fen).map_err(|err| err.to_string())?,
+        None => Fen::start(),
+    };
+
+    let seed = options.seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(1)
+    });
+
+    difftest::run(games, &start, options.load_pgn.as_deref(), seed)
+}
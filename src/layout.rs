@@ -0,0 +1,223 @@
+use crate::coords::BoardCoords;
+
+const PANEL_MIN_WIDTH: f64 = 180.0;
+const PANEL_MAX_WIDTH: f64 = 280.0;
+const CLOCK_HEIGHT: f64 = 60.0;
+const ACTIONS_HEIGHT: f64 = 44.0;
+// Part of the space for the move list that the chat takes
+const CHAT_SHARE: f64 = 0.45;
+const CHAT_INPUT_HEIGHT: f64 = 30.0;
+// A text field and a line for the candidates or the error below it
+const MOVE_INPUT_HEIGHT: f64 = 62.0;
+const MOVE_FIELD_HEIGHT: f64 = 30.0;
+
+pub fn contains([x, y, w, h]: [f64; 4], [px, py]: [f64; 2]) -> bool {
+    px >= x && px < x + w && py >= y && py < y + h
+}
+
+// Where everything goes in a window of a given size. The board is the
+// largest square that fits next to the side panel and is centered in the
+// space that is left, so rendering and hit testing both use this.
+#[derive(Clone, Copy)]
+pub struct Layout {
+    pub window: [f64; 4],
+    pub board: [f64; 4],
+    pub panel: [f64; 4],
+    pub top_clock: [f64; 4],
+    pub move_list: [f64; 4],
+    // Typing moves, below the move list when a human plays here
+    pub move_input: [f64; 4],
+    // Resign and draw offer buttons below the move list
+    pub actions: [f64; 4],
+    // Below the actions in network and hot-seat games, empty otherwise
+    pub chat: [f64; 4],
+    pub bottom_clock: [f64; 4],
+}
+
+impl Layout {
+    pub fn new([width, height]: [f64; 2], clocks: bool, chat: bool, typing: bool) -> Layout {
+        let panel_width = (width * 0.25)
+            .max(PANEL_MIN_WIDTH)
+            .min(PANEL_MAX_WIDTH)
+            .min(width / 2.0);
+
+        let board_area = [width - panel_width, height];
+        let board_size = board_area[0].min(board_area[1]).max(0.0);
+        let board = [
+            (board_area[0] - board_size) / 2.0,
+            (board_area[1] - board_size) / 2.0,
+            board_size,
+            board_size,
+        ];
+
+        let panel = [width - panel_width, 0.0, panel_width, height];
+
+        let (top_clock, move_list, bottom_clock) = match clocks {
+            true => (
+                [panel[0], panel[1], panel[2], CLOCK_HEIGHT],
+                [
+                    panel[0],
+                    panel[1] + CLOCK_HEIGHT,
+                    panel[2],
+                    panel[3] - CLOCK_HEIGHT * 2.0,
+                ],
+                [
+                    panel[0],
+                    panel[1] + panel[3] - CLOCK_HEIGHT,
+                    panel[2],
+                    CLOCK_HEIGHT,
+                ],
+            ),
+            false => ([0.0; 4], panel, [0.0; 4]),
+        };
+
+        let chat_height = match chat {
+            true => ((move_list[3] - ACTIONS_HEIGHT) * CHAT_SHARE).max(0.0),
+            false => 0.0,
+        };
+
+        let move_input_height = match typing {
+            true => MOVE_INPUT_HEIGHT,
+            false => 0.0,
+        };
+
+        let move_list = [
+            move_list[0],
+            move_list[1],
+            move_list[2],
+            (move_list[3] - ACTIONS_HEIGHT - chat_height - move_input_height).max(0.0),
+        ];
+        let move_input = [
+            move_list[0],
+            move_list[1] + move_list[3],
+            move_list[2],
+            move_input_height,
+        ];
+        let actions = [
+            move_input[0],
+            move_input[1] + move_input[3],
+            move_input[2],
+            ACTIONS_HEIGHT,
+        ];
+        let chat = match chat {
+            true => [actions[0], actions[1] + actions[3], actions[2], chat_height],
+            false => [0.0; 4],
+        };
+
+        Layout {
+            window: [0.0, 0.0, width, height],
+            board,
+            panel,
+            top_clock,
+            move_list,
+            move_input,
+            actions,
+            chat,
+            bottom_clock,
+        }
+    }
+
+    pub fn board_coords(&self, flipped: bool) -> BoardCoords {
+        BoardCoords::new(self.board[0], self.board[1], self.board[2], flipped)
+    }
+
+    pub fn board_center(&self) -> [f64; 2] {
+        [
+            self.board[0] + self.board[2] / 2.0,
+            self.board[1] + self.board[3] / 2.0,
+        ]
+    }
+
+    // A dialog card centered on the board, `width` and `height` are fractions
+    // of the board size
+    pub fn dialog(&self, width: f64, height: f64) -> [f64; 4] {
+        let [x, y] = self.board_center();
+        let size = self.board[2];
+
+        [
+            x - size * width / 2.0,
+            y - size * height / 2.0,
+            size * width,
+            size * height,
+        ]
+    }
+
+    // The card shown when the game is over
+    pub fn result_card(&self) -> [f64; 4] {
+        self.dialog(0.66, 0.36)
+    }
+
+    // Rematch, review and save along the bottom of the result card
+    pub fn result_buttons(&self) -> [[f64; 4]; 3] {
+        let [x, y, w, h] = self.result_card();
+        let padding = w * 0.04;
+        let height = h * 0.22;
+        let width = (w - padding * 4.0) / 3.0;
+
+        [0.0, 1.0, 2.0].map(|i| {
+            [
+                x + padding + i * (width + padding),
+                y + h - padding - height,
+                width,
+                height,
+            ]
+        })
+    }
+
+    pub fn action_buttons(&self) -> [[f64; 4]; 2] {
+        let [x, y, w, h] = self.actions;
+        let padding = 6.0;
+        let width = (w - padding * 3.0) / 2.0;
+
+        [0.0, 1.0].map(|i| {
+            [
+                x + padding + i * (width + padding),
+                y + padding,
+                width,
+                h - padding * 2.0,
+            ]
+        })
+    }
+
+    pub fn move_field(&self) -> [f64; 4] {
+        let [x, y, w, _] = self.move_input;
+        let padding = 8.0;
+
+        [
+            x + padding,
+            y + padding,
+            (w - padding * 2.0).max(0.0),
+            MOVE_FIELD_HEIGHT,
+        ]
+    }
+
+    // The text field along the bottom of the chat
+    pub fn chat_input(&self) -> [f64; 4] {
+        let [x, y, w, h] = self.chat;
+        let padding = 8.0;
+
+        [
+            x + padding,
+            y + h - padding - CHAT_INPUT_HEIGHT,
+            (w - padding * 2.0).max(0.0),
+            CHAT_INPUT_HEIGHT,
+        ]
+    }
+
+    // The yes and no buttons of the confirmation dialog
+    pub fn confirm_buttons(&self) -> [[f64; 4]; 2] {
+        let [x, y, w, h] = self.dialog(0.6, 0.3);
+        let padding = w * 0.05;
+        let height = h * 0.28;
+        let width = (w - padding * 3.0) / 2.0;
+
+        [0.0, 1.0].map(|i| {
+            [
+                x + padding + i * (width + padding),
+                y + h - padding - height,
+                width,
+                height,
+            ]
+        })
+    }
+}
@@ -1,24 +1,63 @@
+use std::time::Duration;
+
 use glutin_window::GlutinWindow as Window;
 use opengl_graphics::{GlGraphics, OpenGL};
 
 use piston::event_loop::{EventSettings, Events};
 use piston::input::{RenderEvent, UpdateEvent};
+use piston::window::Window as _;
 use piston::window::WindowSettings;
 
+mod ai;
 mod animation;
+mod backend;
+mod chat;
 mod chess_controller;
 mod chess_renderer;
+mod clipboard;
+mod clock;
+mod coords;
+mod dialog;
+mod difftest;
+mod fen;
+mod game_picker;
+mod game_result;
+mod headless;
+mod layout;
+mod match_runner;
+mod move_input;
+mod network;
+mod options;
+mod pgn;
+mod player;
+mod rules;
+mod san;
+mod text_input;
+mod uci;
+mod zobrist;
 
 fn main() {
+    let options = match options::Options::from_args(std::env::args()) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(task) = options.headless.clone() {
+        std::process::exit(headless::run(task, options));
+    }
+
     // Change this to OpenGL::V2_1 if not working.
     let opengl = OpenGL::V3_2;
 
     // Create a Glutin window.
-    let mut window: Window = WindowSettings::new("Chess", [600, 600])
+    let mut window: Window = WindowSettings::new("Chess", [800, 600])
         .samples(1)
         .graphics_api(opengl)
         .exit_on_esc(false)
-        .resizable(false)
+        .resizable(true)
         .build()
         .unwrap();
 
@@ -26,10 +65,44 @@ fn main() {
     let mut chess_renderer = chess_renderer::ChessRenderer::new(GlGraphics::new(opengl));
     let mut events = Events::new(EventSettings::new());
 
-    let mut chess_controller = chess_controller::ChessController::new();
+    let mut match_runner = match options.match_games {
+        Some(games) => match match_runner::MatchRunner::new(options.clone(), games) {
+            Ok(runner) => Some(runner),
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let mut chess_controller = match chess_controller::ChessController::new(options) {
+        Ok(controller) => controller,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    if chess_controller.options.load_pgn.is_some() {
+        chess_controller.load_pgn_file();
+    }
+    if let Some(runner) = &mut match_runner {
+        runner.start_game(&mut chess_controller);
+    }
+
+    let mut network_game = chess_controller.options.network.clone().map(|role| {
+        let name = chess_controller.local_name.clone();
+        let abandon_after = chess_controller
+            .options
+            .abandon_after
+            .unwrap_or(network::DEFAULT_ABANDON_SECONDS);
+        let abandon_after = Duration::from_secs(abandon_after);
+        network::NetworkGame::start(role, name, abandon_after, &mut chess_controller)
+    });
 
     while let Some(e) = events.next(&mut window) {
-        chess_controller.event([600, 600], &e);
+        let size = window.size();
+        chess_controller.event([size.width, size.height], &e);
 
         if let Some(args) = e.render_args() {
             chess_renderer.render(&args, &chess_controller);
@@ -37,7 +110,22 @@ fn main() {
 
         if let Some(args) = e.update_args() {
             chess_controller.update(&args);
+            if let Some(runner) = &mut match_runner {
+                runner.update(&mut chess_controller, args.dt);
+            }
+            if let Some(network_game) = &mut network_game {
+                network_game.update(&mut chess_controller);
+            }
             chess_renderer.update(&args);
         }
     }
+
+    if let Some(network_game) = &mut network_game {
+        network_game.close();
+    }
+
+    // Matches save every game as soon as it ends
+    if chess_controller.options.save_pgn.is_some() && match_runner.is_none() {
+        chess_controller.save_pgn();
+    }
 }
@@ -0,0 +1,286 @@
+use std::path::{Path, PathBuf};
+
+use ChessAPI::piece::*;
+
+use crate::backend::BackendKind;
+use crate::chess_controller::ChessController;
+use crate::fen::Fen;
+use crate::game_result::{GameResult, Termination};
+use crate::options::Options;
+use crate::pgn;
+use crate::pgn::PgnGame;
+
+// Games that run this long are adjudicated as draws
+const MAX_PLIES: usize = 500;
+
+// Results from the point of view of the first player
+#[derive(Clone, Copy, Default)]
+pub struct MatchScore {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl MatchScore {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    pub fn score(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games() as f64
+    }
+
+    // Elo difference between the players and the margin of its 95% confidence
+    // interval, None until both won and lost at least some points
+    pub fn elo(&self) -> Option<(f64, f64)> {
+        let n = self.games() as f64;
+        let score = self.score();
+        if self.games() == 0 || score <= 0.0 || score >= 1.0 {
+            return None;
+        }
+
+        let variance = (self.wins as f64 * (1.0 - score).powi(2)
+            + self.draws as f64 * (0.5 - score).powi(2)
+            + self.losses as f64 * score.powi(2))
+            / n;
+        let deviation = (variance / n).sqrt();
+
+        let low = elo_difference(score - 1.96 * deviation);
+        let high = elo_difference(score + 1.96 * deviation);
+
+        Some((elo_difference(score), (high - low) / 2.0))
+    }
+}
+
+fn elo_difference(score: f64) -> f64 {
+    let score = score.clamp(0.001, 0.999);
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+// Openings are either games in a PGN file, whose moves are played before the
+// players take over, or a file with one FEN or EPD position per line
+pub fn load_openings(path: &Path, kind: BackendKind) -> Result<Vec<PgnGame>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+
+    let pgn = path
+        .extension()
+        .map_or(false, |extension| extension.eq_ignore_ascii_case("pgn"));
+
+    let openings = if pgn {
+        let games = pgn::parse_pgn(&text).map_err(|err| err.to_string())?;
+        for (i, game) in games.iter().enumerate() {
+            game.legal_moves(i, kind).map_err(|err| err.to_string())?;
+        }
+        games
+    } else {
+        text.lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                // EPD operations come after the first four fields
+                let fields = line.split_whitespace().take(4).collect::<Vec<&str>>();
+                let fen = Fen::parse(&fields.join(" ")).map_err(|err| err.to_string())?;
+
+                let mut opening = PgnGame::empty();
+                opening.set_tag("SetUp", "1");
+                opening.set_tag("FEN", &fen.to_string());
+                Ok(opening)
+            })
+            .collect::<Result<Vec<PgnGame>, String>>()?
+    };
+
+    if openings.is_empty() {
+        return Err(format!("{} does not contain any openings", path.display()));
+    }
+
+    Ok(openings)
+}
+
+// Plays a number of games between the two players given in the options,
+// swapping colors after every game. Each opening is played twice, once with
+// each player as White.
+pub struct MatchRunner {
+    options: Options,
+    games: u32,
+    openings: Vec<PgnGame>,
+    output: PathBuf,
+    names: [String; 2],
+    pub score: MatchScore,
+    pub finished: Vec<PgnGame>,
+    // Seconds to look at the final position before the next game starts
+    pub pause_between_games: f64,
+    pause: Option<f64>,
+}
+
+impl MatchRunner {
+    pub fn new(options: Options, games: u32) -> Result<MatchRunner, String> {
+        let openings = match &options.openings {
+            Some(path) => load_openings(path, options.backend)?,
+            None => vec![PgnGame::empty()],
+        };
+
+        let output = options
+            .save_pgn
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("match.pgn"));
+
+        Ok(MatchRunner {
+            options,
+            games,
+            openings,
+            output,
+            names: [String::new(), String::new()],
+            score: MatchScore::default(),
+            finished: Vec::new(),
+            pause_between_games: 2.0,
+            pause: None,
+        })
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.finished.len() as u32 >= self.games
+    }
+
+    // The first player has White in even games
+    fn first_player_white(&self) -> bool {
+        self.finished.len() % 2 == 0
+    }
+
+    pub fn start_game(&mut self, controller: &mut ChessController) {
+        let index = self.finished.len();
+        let opening = &self.openings[index / 2 % self.openings.len()];
+
+        let mut options = self.options.clone();
+        options.fen = opening.tag("FEN").map(|fen| fen.to_string());
+        if !self.first_player_white() {
+            options.players.swap(0, 1);
+        }
+
+        let (flipped, auto_flip) = (controller.flipped, controller.auto_flip);
+        *controller = match ChessController::new(options) {
+            Ok(controller) => controller,
+            Err(err) => {
+                controller.show_error(err);
+                return;
+            }
+        };
+        controller.flipped = flipped;
+        controller.auto_flip = auto_flip;
+
+        let played = opening
+            .legal_moves(index / 2 % self.openings.len(), controller.options.backend)
+            .map_err(|err| err.to_string())
+            .and_then(|moves| controller.play_opening(&moves));
+        if let Err(err) = played {
+            controller.show_error(err);
+        }
+
+        if index == 0 {
+            self.names = [controller.players[0].name(), controller.players[1].name()];
+        }
+
+        let standings = self.standings();
+        controller.status = Some(format!("Game {} of {}, {}", index + 1, self.games, standings));
+    }
+
+    pub fn standings(&self) -> String {
+        format!(
+            "{} {} - {} {} ({} draws)",
+            self.names[0],
+            self.score.wins as f64 + self.score.draws as f64 / 2.0,
+            self.score.losses as f64 + self.score.draws as f64 / 2.0,
+            self.names[1],
+            self.score.draws
+        )
+    }
+
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "Score of {} vs {}: {} - {} - {} [{:.3}] {}",
+            self.names[0],
+            self.names[1],
+            self.score.wins,
+            self.score.losses,
+            self.score.draws,
+            self.score.score(),
+            self.score.games()
+        );
+
+        match self.score.elo() {
+            Some((elo, margin)) => {
+                summary.push_str(&format!("\nElo difference: {:.1} +/- {:.1}", elo, margin))
+            }
+            None => summary.push_str("\nElo difference: not enough decisive games"),
+        }
+
+        summary
+    }
+
+    pub fn update(&mut self, controller: &mut ChessController, dt: f64) {
+        if self.is_done() {
+            return;
+        }
+
+        if controller.game_result.is_none() && controller.history.len() >= MAX_PLIES {
+            controller.end_game(GameResult::draw(Termination::Adjudication));
+        }
+
+        if controller.game_result.is_none() {
+            return;
+        }
+
+        let pause = self.pause.get_or_insert(self.pause_between_games);
+        *pause -= dt;
+        if *pause > 0.0 {
+            return;
+        }
+        self.pause = None;
+
+        self.finish_game(controller);
+
+        if self.is_done() {
+            let summary = self.summary();
+            println!("{}", summary);
+            // The status line only has room for one line
+            controller.status = Some(summary.replace('\n', ", "));
+        } else {
+            self.start_game(controller);
+        }
+    }
+
+    fn finish_game(&mut self, controller: &mut ChessController) {
+        // Engines only tell their names once they are running
+        if self.finished.is_empty() {
+            self.names = [controller.players[0].name(), controller.players[1].name()];
+        }
+
+        let first_player_won = controller
+            .game_result
+            .and_then(|result| result.winner)
+            .map(|winner| (winner == Color::White) == self.first_player_white());
+
+        match first_player_won {
+            Some(true) => self.score.wins += 1,
+            Some(false) => self.score.losses += 1,
+            None => self.score.draws += 1,
+        }
+
+        let mut game = controller.to_pgn();
+        game.set_tag("Event", "Engine match");
+        game.set_tag("Round", &(self.finished.len() + 1).to_string());
+        self.finished.push(game);
+
+        println!(
+            "Game {} {}: {}",
+            self.finished.len(),
+            controller.result(),
+            self.standings()
+        );
+
+        // Written after every game so an interrupted match is not lost
+        if let Err(err) = pgn::write_pgn_file(&self.output, &self.finished) {
+            controller.show_error(err);
+        }
+    }
+}
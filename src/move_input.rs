@@ -0,0 +1,270 @@
+use piston::input::*;
+use piston::Event;
+
+use ChessAPI::board::*;
+
+use crate::ai;
+use crate::backend::ChessBackend;
+use crate::layout;
+use crate::san;
+use crate::san::SanError;
+use crate::text_input::{InputEvent, TextInput};
+use crate::uci;
+
+const MAX_MOVE_CHARS: usize = 10;
+
+// Moves typed instead of clicked, in SAN like "Nf3" and "e8=Q" or in long
+// algebraic notation like "g1f3"
+pub struct MoveInput {
+    pub input: TextInput,
+    pub focused: bool,
+    // The legal moves in SAN that start like the text, Tab goes through them
+    pub candidates: Vec<String>,
+    pub selected: usize,
+    // Why the last move typed could not be played
+    pub error: Option<String>,
+    // The text and position the candidates were found for
+    found_for: Option<(String, String)>,
+}
+
+impl MoveInput {
+    pub fn new() -> MoveInput {
+        MoveInput {
+            input: TextInput::new(MAX_MOVE_CHARS),
+            focused: false,
+            candidates: Vec::new(),
+            selected: 0,
+            error: None,
+            found_for: None,
+        }
+    }
+
+    // Clicking the field or pressing Tab starts typing a move, Tab then fills
+    // in the candidates one after another. Returns None if the event was not
+    // used, playing the move and errors are left to the owner
+    pub fn event(
+        &mut self,
+        e: &Event,
+        field: [f64; 4],
+        cursor: [f64; 2],
+        ctrl_pressed: bool,
+        may_start: bool,
+    ) -> Option<InputEvent> {
+        if let Some(Button::Mouse(MouseButton::Left)) = e.press_args() {
+            self.focused = layout::contains(field, cursor);
+            return self.focused.then_some(InputEvent::Handled);
+        }
+
+        if !self.focused {
+            let start = e.press_args() == Some(Button::Keyboard(Key::Tab));
+            self.focused = start && may_start;
+            return self.focused.then_some(InputEvent::Handled);
+        }
+
+        match e.press_args() {
+            Some(Button::Keyboard(Key::Tab)) => self.complete(),
+            _ => match self.input.event(e, ctrl_pressed) {
+                InputEvent::Ignored => return None,
+                InputEvent::Edited => self.error = None,
+                InputEvent::Cancel => {
+                    self.focused = false;
+                    self.error = None;
+                }
+                event => return Some(event),
+            },
+        }
+
+        Some(InputEvent::Handled)
+    }
+
+    // Finds the candidates again when the text or the position changed,
+    // `fen` tells positions apart
+    pub fn refresh(&mut self, board: &dyn ChessBackend, fen: String) {
+        let found_for = Some((self.input.text.clone(), fen));
+        if self.found_for == found_for {
+            return;
+        }
+
+        self.candidates = candidates(board, &self.input.text);
+        self.selected = 0;
+        self.found_for = found_for;
+    }
+
+    // Fills in the next candidate, keeping the list so that pressing Tab
+    // again moves on to the one after it
+    pub fn complete(&mut self) {
+        if self.candidates.is_empty() {
+            return;
+        }
+
+        let candidate = &self.candidates[self.selected % self.candidates.len()];
+        self.input.set(candidate);
+        self.selected = (self.selected + 1) % self.candidates.len();
+
+        if let Some((text, _)) = &mut self.found_for {
+            *text = self.input.text.clone();
+        }
+    }
+}
+
+// The move `text` stands for, or what is wrong with it
+pub fn parse_move(board: &dyn ChessBackend, text: &str) -> Result<Move, String> {
+    let text = normalize(text);
+    if text.is_empty() {
+        return Err("Type a move like Nf3 or g1f3".to_string());
+    }
+
+    if let Some(mv) = uci::parse_uci_move(board, &text.replace('-', "")) {
+        return Ok(mv);
+    }
+
+    san::parse_san(board, &text).map_err(|err| match err {
+        SanError::Invalid => format!("{} is not a move", text),
+        SanError::Illegal => format!("{} is not legal here", text),
+        SanError::Ambiguous => {
+            let options = legal_moves(board)
+                .iter()
+                .map(|mv| san::move_to_san(board, mv))
+                .filter(|san| without_origin(san) == without_origin(&text))
+                .collect::<Vec<String>>();
+            format!(
+                "{} is ambiguous, it could be {}",
+                text,
+                options.join(" or ")
+            )
+        }
+        SanError::MissingPromotion => {
+            format!("{} needs a piece to promote to, like {}=Q", text, text)
+        }
+    })
+}
+
+// The legal moves in SAN that start like `text`, or whose long algebraic
+// notation does. Captures and checks do not have to be typed.
+pub fn candidates(board: &dyn ChessBackend, text: &str) -> Vec<String> {
+    let text = normalize(text);
+    if text.is_empty() {
+        return Vec::new();
+    }
+
+    let (uci_text, san_text) = (text.replace('-', ""), plain(&text));
+
+    let mut candidates = legal_moves(board)
+        .iter()
+        .filter_map(|mv| {
+            let san = san::move_to_san(board, mv);
+            let typed =
+                uci::move_to_uci(mv).starts_with(&uci_text) || plain(&san).starts_with(&san_text);
+            match typed {
+                true => Some(san),
+                false => None,
+            }
+        })
+        .collect::<Vec<String>>();
+
+    candidates.sort();
+    candidates.dedup();
+    candidates
+}
+
+// One move per promotion piece, so that every move can be written in SAN
+fn legal_moves(board: &dyn ChessBackend) -> Vec<Move> {
+    ai::expand_promotions(board, board.generate_legal_moves())
+}
+
+// Allows lowercase pieces other than the bishop, which could be the b-file,
+// and castling typed with zeros or without hyphens
+fn normalize(text: &str) -> String {
+    let text = text.trim();
+    if !text.is_empty() && text.chars().all(|c| matches!(c, 'o' | 'O' | '0' | '-')) {
+        let castle = text.replace(['o', '0'], "O");
+        return match castle.replace('-', "").as_str() {
+            "OO" => "O-O".to_string(),
+            "OOO" => "O-O-O".to_string(),
+            _ => castle,
+        };
+    }
+
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(c @ ('n' | 'r' | 'q' | 'k')) => {
+            format!("{}{}", c.to_ascii_uppercase(), chars.as_str())
+        }
+        _ => text.to_string(),
+    }
+}
+
+fn plain(san: &str) -> String {
+    san.chars()
+        .filter(|c| !matches!(c, 'x' | '+' | '#' | '!' | '?'))
+        .collect()
+}
+
+// "Nbd2" and "Nd2" both become "Nd2"
+fn without_origin(san: &str) -> String {
+    let san = plain(san);
+    let piece = san
+        .chars()
+        .next()
+        .filter(|c| c.is_ascii_uppercase() && *c != 'O');
+
+    match piece {
+        Some(piece) if san.len() >= 3 && san.is_ascii() => {
+            format!("{}{}", piece, &san[san.len() - 2..])
+        }
+        _ => san,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{BackendKind, Board};
+    use crate::fen::Fen;
+
+    fn board(kind: BackendKind, fen: &str) -> Board {
+        let fen = Fen::parse(fen).unwrap_or_else(|err| panic!("{}", err));
+        kind.board_from_fen(&fen).unwrap()
+    }
+
+    #[test]
+    fn promotions_are_suggested_with_every_piece() {
+        for kind in BackendKind::ALL {
+            let board = board(kind, "8/4P3/8/8/8/8/k7/4K3 w - - 0 1");
+            assert_eq!(
+                candidates(board.as_ref(), "e8"),
+                ["e8=B", "e8=N", "e8=Q", "e8=R"],
+                "{}",
+                kind.name()
+            );
+        }
+    }
+
+    #[test]
+    fn typed_promotions_can_be_played() {
+        for kind in BackendKind::ALL {
+            let mut board = board(kind, "8/4P3/8/8/8/8/k7/4K3 w - - 0 1");
+            let mv = parse_move(board.as_ref(), "e8=N").unwrap();
+            assert_eq!(board.make_move(&mv), Ok(()), "{}", kind.name());
+            assert_eq!(board.fen(), "4N3/8/8/8/8/8/k7/4K3 b - - 0 1", "{}", kind.name());
+        }
+    }
+
+    #[test]
+    fn castling_can_be_typed_without_hyphens() {
+        let board = board(BackendKind::ChessApi, "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        for (text, san) in [("oo", "O-O"), ("00", "O-O"), ("ooo", "O-O-O"), ("0-0-0", "O-O-O")] {
+            let mv = parse_move(board.as_ref(), text).unwrap_or_else(|err| panic!("{}", err));
+            assert_eq!(san::move_to_san(board.as_ref(), &mv), san, "{}", text);
+        }
+        assert_eq!(candidates(board.as_ref(), "oo"), ["O-O", "O-O-O"]);
+    }
+
+    #[test]
+    fn ambiguous_moves_list_the_options() {
+        let board = board(BackendKind::ChessApi, "4k3/8/8/8/8/8/4K3/R6R w - - 0 1");
+        let err = parse_move(board.as_ref(), "Rd1").err().unwrap();
+        assert!(err.starts_with("Rd1 is ambiguous"), "{}", err);
+        assert!(err.contains("Rad1") && err.contains("Rhd1"), "{}", err);
+    }
+}
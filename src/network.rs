@@ -0,0 +1,1442 @@
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use ChessAPI::board::*;
+use ChessAPI::piece::*;
+
+use crate::ai::Random;
+use crate::chess_controller::ChessController;
+use crate::clock::{color_index, TimeControl};
+use crate::fen;
+use crate::game_result::{GameResult, Termination};
+use crate::player::{HumanPlayer, RemotePlayer};
+use crate::uci;
+
+// Peers with a different version are turned away during the hello
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const DEFAULT_PORT: u16 = 7878;
+// How long a player who lost the connection has to come back
+pub const DEFAULT_ABANDON_SECONDS: u64 = 60;
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(2);
+// Both sides ping each other, a connection that stays quiet for longer than
+// the timeout is taken as lost even when TCP has not noticed yet
+const PING_INTERVAL: Duration = Duration::from_secs(2);
+const PING_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone)]
+pub enum NetworkRole {
+    // Listens on a port and plays `Color`
+    Host(u16, Color),
+    // Connects to an address like "192.168.1.2:7878"
+    Join(String),
+    // Connects to a host to follow its game without playing
+    Watch(String),
+}
+
+// One line of text per message, the first word says what it is
+pub enum Message {
+    // The id stays the same when a player connects again
+    Hello {
+        version: u32,
+        id: u64,
+        spectator: bool,
+        name: String,
+    },
+    // Sent by the host after the hello, the color is the one the other side
+    // plays
+    Game {
+        color: Color,
+        time_control: Option<TimeControl>,
+        fen: String,
+    },
+    // Sent by the host after the game to a player that comes back or a
+    // spectator, everything needed to pick the game up: the clocks in
+    // milliseconds and every move so far
+    Resume {
+        clock: Option<(u64, u64)>,
+        moves: Vec<String>,
+    },
+    // In long algebraic notation
+    Move(String),
+    Resign,
+    DrawOffer,
+    DrawAccept,
+    DrawDecline,
+    // Milliseconds left for White and Black after the sender's last move
+    Clock(u64, u64),
+    // How the game ended after the given number of plies. Resignations and
+    // agreed draws have their own messages, and only the host's clock runs out
+    Result(GameResult, usize),
+    Chat(String),
+    // Keeps a quiet connection alive
+    Ping,
+    // Leaving on purpose
+    Bye,
+}
+
+fn color_word(color: Color) -> &'static str {
+    match color {
+        Color::White => "white",
+        Color::Black => "black",
+    }
+}
+
+// Undoes the escaping of line breaks and backslashes in chat messages
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+
+    out
+}
+
+impl Message {
+    pub fn parse(line: &str) -> Result<Message, String> {
+        let invalid = || format!("Invalid message \"{}\"", line);
+        let (kind, rest) = line
+            .trim_end()
+            .split_once(' ')
+            .unwrap_or((line.trim_end(), ""));
+        let number = |word: Option<&str>| {
+            word.and_then(|word| word.parse::<u64>().ok())
+                .ok_or_else(invalid)
+        };
+
+        Ok(match kind {
+            "HELLO" => {
+                let mut words = rest.splitn(4, ' ');
+                Message::Hello {
+                    version: u32::try_from(number(words.next())?).map_err(|_| invalid())?,
+                    id: number(words.next())?,
+                    spectator: match words.next() {
+                        Some("player") => false,
+                        Some("spectator") => true,
+                        _ => return Err(invalid()),
+                    },
+                    name: words.next().unwrap_or("").to_string(),
+                }
+            }
+            "GAME" => {
+                let mut words = rest.splitn(3, ' ');
+                let color = match words.next() {
+                    Some("white") => Color::White,
+                    Some("black") => Color::Black,
+                    _ => return Err(invalid()),
+                };
+                let time_control = match words.next().ok_or_else(invalid)? {
+                    "-" => None,
+                    text => Some(TimeControl::parse(text)?),
+                };
+                Message::Game {
+                    color,
+                    time_control,
+                    fen: words.next().ok_or_else(invalid)?.to_string(),
+                }
+            }
+            "RESUME" => {
+                let mut words = rest.split_whitespace();
+                let clock = match (words.next(), words.next()) {
+                    (Some("-"), Some("-")) => None,
+                    (white, black) => Some((number(white)?, number(black)?)),
+                };
+                Message::Resume {
+                    clock,
+                    moves: words.map(|mv| mv.to_string()).collect(),
+                }
+            }
+            "MOVE" if !rest.is_empty() => Message::Move(rest.to_string()),
+            "RESIGN" => Message::Resign,
+            "DRAW" => match rest {
+                "OFFER" => Message::DrawOffer,
+                "ACCEPT" => Message::DrawAccept,
+                "DECLINE" => Message::DrawDecline,
+                _ => return Err(invalid()),
+            },
+            "CLOCK" => {
+                let mut words = rest.split(' ');
+                Message::Clock(number(words.next())?, number(words.next())?)
+            }
+            "RESULT" => {
+                let mut words = rest.split(' ');
+                let score = words.next().ok_or_else(invalid)?;
+                let termination = words.next().and_then(Termination::from_word);
+                let termination = termination.ok_or_else(invalid)?;
+                let result = GameResult::from_score(score, termination).ok_or_else(invalid)?;
+                Message::Result(result, number(words.next())? as usize)
+            }
+            "CHAT" => Message::Chat(unescape(rest)),
+            "PING" => Message::Ping,
+            "BYE" => Message::Bye,
+            _ => return Err(invalid()),
+        })
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Message::Hello {
+                version,
+                id,
+                spectator,
+                name,
+            } => {
+                let role = if *spectator { "spectator" } else { "player" };
+                write!(f, "HELLO {} {} {} {}", version, id, role, name)
+            }
+            Message::Game {
+                color,
+                time_control,
+                fen,
+            } => {
+                let time_control = time_control.as_ref().map(|tc| tc.to_string());
+                let time_control = time_control.as_deref().unwrap_or("-");
+                write!(f, "GAME {} {} {}", color_word(*color), time_control, fen)
+            }
+            Message::Resume { clock, moves } => {
+                match clock {
+                    Some((white, black)) => write!(f, "RESUME {} {}", white, black)?,
+                    None => write!(f, "RESUME - -")?,
+                }
+                moves.iter().try_for_each(|mv| write!(f, " {}", mv))
+            }
+            Message::Move(mv) => write!(f, "MOVE {}", mv),
+            Message::Resign => write!(f, "RESIGN"),
+            Message::DrawOffer => write!(f, "DRAW OFFER"),
+            Message::DrawAccept => write!(f, "DRAW ACCEPT"),
+            Message::DrawDecline => write!(f, "DRAW DECLINE"),
+            Message::Clock(white, black) => write!(f, "CLOCK {} {}", white, black),
+            Message::Result(result, plies) => write!(
+                f,
+                "RESULT {} {} {}",
+                result.score(),
+                result.termination.word(),
+                plies
+            ),
+            // Chat messages are kept on a single line
+            Message::Chat(text) => {
+                let text = text.replace('\\', "\\\\").replace('\n', "\\n");
+                write!(f, "CHAT {}", text)
+            }
+            Message::Ping => write!(f, "PING"),
+            Message::Bye => write!(f, "BYE"),
+        }
+    }
+}
+
+// A TCP connection to the other side. Lines are read on a separate thread,
+// `poll` picks up the messages that arrived since the last update.
+pub struct Connection {
+    stream: TcpStream,
+    messages: Receiver<Result<Message, String>>,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> Result<Connection, String> {
+        let reader = stream.try_clone().map_err(|err| err.to_string())?;
+
+        let (sender, messages) = channel();
+        thread::spawn(move || {
+            for line in BufReader::new(reader).lines() {
+                let message = match line {
+                    Ok(line) => Message::parse(&line),
+                    Err(_) => break,
+                };
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Connection { stream, messages })
+    }
+
+    pub fn send(&mut self, message: &Message) -> Result<(), String> {
+        writeln!(self.stream, "{}", message).map_err(|err| err.to_string())
+    }
+
+    // An error means the connection is gone
+    pub fn poll(&mut self) -> Result<Option<Message>, String> {
+        match self.messages.try_recv() {
+            Ok(message) => message.map(Some),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err("the connection was closed".to_string()),
+        }
+    }
+}
+
+// Also stops the thread reading the lines
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+// Listens for connections, or connects once when joining, on a background
+// thread so the window stays responsive while waiting
+fn start_connecting(role: &NetworkRole) -> Receiver<Result<TcpStream, String>> {
+    let (sender, streams) = channel();
+
+    match role.clone() {
+        NetworkRole::Host(port, _) => {
+            thread::spawn(move || {
+                let listener = match TcpListener::bind(("0.0.0.0", port)) {
+                    Ok(listener) => listener,
+                    Err(err) => {
+                        let _ = sender.send(Err(format!("Could not listen on {}: {}", port, err)));
+                        return;
+                    }
+                };
+
+                for stream in listener.incoming() {
+                    let stream = stream.map_err(|err| err.to_string());
+                    if sender.send(stream).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        NetworkRole::Join(address) | NetworkRole::Watch(address) => {
+            thread::spawn(move || {
+                let stream = TcpStream::connect(&address)
+                    .map_err(|err| format!("Could not connect to {}: {}", address, err));
+                let _ = sender.send(stream);
+            });
+        }
+    }
+
+    streams
+}
+
+// A number that tells this instance apart from others, so that a player who
+// lost the connection can be recognized when they come back
+fn new_peer_id() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(1);
+
+    Random((nanos ^ (std::process::id() as u64) << 32).max(1)).next()
+}
+
+// Where a player keeps their side of the game, one file per host port or
+// address so that several games on one machine do not mix. Spectators get
+// everything from the host again.
+fn saved_game_path(role: &NetworkRole) -> Option<PathBuf> {
+    match role {
+        NetworkRole::Host(port, _) => Some(PathBuf::from(format!("network-host-{}.txt", port))),
+        NetworkRole::Join(address) => Some(PathBuf::from(format!(
+            "network-join-{}.txt",
+            address.replace([':', '/', '\\'], "-")
+        ))),
+        NetworkRole::Watch(_) => None,
+    }
+}
+
+// Keeps a game in sync with the other side, updated after the controller
+// every frame like a match runner. Local moves, resignations and draw offers
+// are noticed in the controller and sent, messages from the other side are
+// played into it.
+//
+// The game itself, its moves and clocks, stays in the controller when the
+// connection drops. Together with the colors and the peer id kept here that
+// is enough to pick the game up again: a joining player keeps trying to
+// connect, the host keeps listening and sends the whole game to the player
+// that comes back. All of it is also saved after every move, so that a
+// process that was restarted with the same options comes back the same way.
+//
+// The host also keeps a list of spectators, who get the whole game when they
+// connect and every move and result after that.
+pub struct NetworkGame {
+    role: NetworkRole,
+    name: String,
+    id: u64,
+    abandon_after: Duration,
+    streams: Receiver<Result<TcpStream, String>>,
+    // Connections that have not said hello yet
+    pending: Vec<Connection>,
+    connection: Option<Connection>,
+    spectators: Vec<Connection>,
+    // Moves sent to the spectators
+    broadcast_plies: usize,
+    broadcast_result: bool,
+    // Set once a spectator got the game from the host
+    watching: bool,
+    // Known once both sides said hello and the game was set up
+    pub local_color: Option<Color>,
+    pub peer_name: Option<String>,
+    peer_id: Option<u64>,
+    // Feeds the moves from the other side to its RemotePlayer
+    moves: Option<Sender<String>>,
+    // Plies both sides know about
+    plies: usize,
+    // The controller's draw offer as of the last update
+    draw_offer: Option<Color>,
+    // The clocks sent with the other side's last move
+    clock: Option<[f64; 2]>,
+    result_sent: bool,
+    // The result from the other side and the plies it came after
+    remote_result: Option<(GameResult, usize)>,
+    // When the connection to the other side was lost during the game
+    lost_at: Option<Instant>,
+    // When a joining player tries to connect again
+    retry_at: Option<Instant>,
+    // When the other side was last heard from, and when it is pinged next
+    heard_at: Instant,
+    ping_at: Instant,
+    // The plies and whether the game was over when it was last saved
+    saved: Option<(usize, bool)>,
+}
+
+impl NetworkGame {
+    pub fn start(
+        role: NetworkRole,
+        name: String,
+        abandon_after: Duration,
+        controller: &mut ChessController,
+    ) -> NetworkGame {
+        let mut game = NetworkGame {
+            streams: start_connecting(&role),
+            role,
+            name,
+            id: new_peer_id(),
+            abandon_after,
+            pending: Vec::new(),
+            connection: None,
+            spectators: Vec::new(),
+            broadcast_plies: 0,
+            broadcast_result: false,
+            watching: false,
+            local_color: None,
+            peer_name: None,
+            peer_id: None,
+            moves: None,
+            plies: 0,
+            draw_offer: None,
+            clock: None,
+            result_sent: false,
+            remote_result: None,
+            lost_at: None,
+            retry_at: None,
+            heard_at: Instant::now(),
+            ping_at: Instant::now(),
+            saved: None,
+        };
+
+        game.load(controller);
+        game
+    }
+
+    // Picks up the game saved by an earlier run, which then waits for the
+    // other side to come back like after a lost connection
+    fn load(&mut self, controller: &mut ChessController) {
+        let path = match saved_game_path(&self.role) {
+            Some(path) => path,
+            None => return,
+        };
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(_) => return,
+        };
+
+        // Saved as the messages that set up a game, see `save`
+        let messages = text
+            .lines()
+            .map(Message::parse)
+            .collect::<Result<Vec<Message>, String>>()
+            .ok()
+            .and_then(|messages| <[Message; 4]>::try_from(messages).ok());
+        let [own, peer, game, resume] = match messages {
+            Some(messages) => messages,
+            None => {
+                controller.show_error(format!("{} is not a saved game", path.display()));
+                return;
+            }
+        };
+
+        let (peer_id, local_color, clock, moves) = match (own, peer, game, resume) {
+            (
+                Message::Hello { id, .. },
+                Message::Hello {
+                    id: peer_id, name, ..
+                },
+                Message::Game {
+                    color,
+                    time_control,
+                    fen,
+                },
+                Message::Resume { clock, moves },
+            ) => {
+                self.id = id;
+                self.peer_name = Some(name);
+                controller.options.time_control = time_control;
+                if !controller.set_position(&fen) {
+                    return;
+                }
+                (peer_id, color, clock, moves)
+            }
+            _ => {
+                controller.show_error(format!("{} is not a saved game", path.display()));
+                return;
+            }
+        };
+
+        if let Err(err) = controller.replay_uci_moves(&moves) {
+            controller.show_error(format!("Could not resume the saved game: {}", err));
+            return;
+        }
+
+        if let (Some((white, black)), Some(clock)) = (clock, controller.live_clock_mut()) {
+            clock.remaining = [white as f64 / 1000.0, black as f64 / 1000.0];
+            clock.running = false;
+        }
+
+        self.connect_players(controller, local_color);
+        controller.flipped = local_color == Color::Black;
+
+        self.peer_id = Some(peer_id);
+        self.local_color = Some(local_color);
+        self.plies = moves.len();
+        self.broadcast_plies = moves.len();
+        self.saved = Some((moves.len(), false));
+        self.lost_at = Some(Instant::now());
+        controller.status = Some(format!("Waiting for {} to resume the game", self.peer()));
+    }
+
+    // Keeps this side of the game on disk after every move, and removes it
+    // once the game is over and there is nothing left to resume
+    fn save(&mut self, controller: &mut ChessController) {
+        let (path, local_color, peer_id) =
+            match (saved_game_path(&self.role), self.local_color, self.peer_id) {
+                (Some(path), Some(local_color), Some(peer_id)) => (path, local_color, peer_id),
+                _ => return,
+            };
+
+        let plies = controller.history.len() + controller.redo_stack.len();
+        let saved = Some((plies, controller.game_result.is_some()));
+        if self.saved == saved {
+            return;
+        }
+        self.saved = saved;
+
+        if controller.game_result.is_some() {
+            let _ = std::fs::remove_file(&path);
+            return;
+        }
+
+        let messages = [
+            Message::Hello {
+                version: PROTOCOL_VERSION,
+                id: self.id,
+                spectator: false,
+                name: self.name.clone(),
+            },
+            Message::Hello {
+                version: PROTOCOL_VERSION,
+                id: peer_id,
+                spectator: false,
+                name: self.peer(),
+            },
+            Message::Game {
+                color: local_color,
+                time_control: controller.options.time_control.clone(),
+                fen: controller.start_fen.to_string(),
+            },
+            resume_message(controller, usize::MAX),
+        ];
+        let text = messages
+            .iter()
+            .map(|message| format!("{}\n", message))
+            .collect::<String>();
+
+        if let Err(err) = std::fs::write(&path, text) {
+            controller.status = Some(format!("Could not save {}: {}", path.display(), err));
+        }
+    }
+
+    pub fn waiting_message(&self) -> String {
+        match &self.role {
+            NetworkRole::Host(port, _) => format!("Waiting for an opponent on port {}", port),
+            NetworkRole::Join(address) | NetworkRole::Watch(address) => {
+                format!("Connecting to {}", address)
+            }
+        }
+    }
+
+    fn is_spectator(&self) -> bool {
+        matches!(self.role, NetworkRole::Watch(_))
+    }
+
+    fn remote_color(&self) -> Option<Color> {
+        self.local_color.map(fen::opposite)
+    }
+
+    fn peer(&self) -> String {
+        self.peer_name
+            .clone()
+            .unwrap_or_else(|| "the other side".to_string())
+    }
+
+    fn send(&mut self, controller: &mut ChessController, message: Message) {
+        let result = match &mut self.connection {
+            Some(connection) => connection.send(&message),
+            None => return,
+        };
+
+        if let Err(err) = result {
+            self.disconnected(controller, err);
+        }
+    }
+
+    fn disconnected(&mut self, controller: &mut ChessController, err: String) {
+        self.connection = None;
+        // Saved again with the clocks as they were when it was lost
+        self.saved = None;
+
+        if self.watching {
+            controller.status = Some(format!("Lost the connection to the game: {}", err));
+        } else if self.local_color.is_none() {
+            controller.show_error(format!("Lost the connection to {}: {}", self.peer(), err));
+        } else if controller.game_result.is_none() {
+            self.lost_at = Some(Instant::now());
+        }
+
+        self.retry_later();
+    }
+
+    // Only players and spectators that joined connect again, the host keeps
+    // listening
+    fn retry_later(&mut self) {
+        if let NetworkRole::Join(_) | NetworkRole::Watch(_) = self.role {
+            self.retry_at = Some(Instant::now() + RECONNECT_INTERVAL);
+        }
+    }
+
+    // Leaves the game, called when the window closes
+    pub fn close(&mut self) {
+        for connection in self.connection.iter_mut().chain(self.spectators.iter_mut()) {
+            let _ = connection.send(&Message::Bye);
+        }
+        self.connection = None;
+        self.spectators = Vec::new();
+
+        // Leaving ends the game, there is nothing to resume
+        if let Some(path) = saved_game_path(&self.role) {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    pub fn update(&mut self, controller: &mut ChessController) {
+        self.connect(controller);
+
+        for mut connection in std::mem::take(&mut self.pending) {
+            loop {
+                match connection.poll() {
+                    Ok(Some(Message::Hello {
+                        version,
+                        id,
+                        spectator,
+                        name,
+                    })) => {
+                        self.hello(controller, connection, version, id, spectator, name);
+                        break;
+                    }
+                    // Nothing else counts before the hello
+                    Ok(Some(_)) => {}
+                    Ok(None) => {
+                        self.pending.push(connection);
+                        break;
+                    }
+                    Err(_) => {
+                        self.retry_later();
+                        break;
+                    }
+                }
+            }
+        }
+
+        while let Some(connection) = &mut self.connection {
+            match connection.poll() {
+                Ok(Some(message)) => {
+                    self.heard_at = Instant::now();
+                    self.receive(controller, message);
+                }
+                Ok(None) => break,
+                Err(err) => self.disconnected(controller, err),
+            }
+        }
+
+        if self.connection.is_some() && self.heard_at.elapsed() > PING_TIMEOUT {
+            let err = format!("no answer for {}s", PING_TIMEOUT.as_secs());
+            self.disconnected(controller, err);
+        }
+
+        // Spectators only ever say goodbye
+        for mut spectator in std::mem::take(&mut self.spectators) {
+            let gone = loop {
+                match spectator.poll() {
+                    Ok(Some(Message::Bye)) | Err(_) => break true,
+                    Ok(Some(_)) => {}
+                    Ok(None) => break false,
+                }
+            };
+
+            if !gone {
+                self.spectators.push(spectator);
+            }
+        }
+
+        if Instant::now() >= self.ping_at {
+            self.ping_at = Instant::now() + PING_INTERVAL;
+            self.send(controller, Message::Ping);
+            self.spectators
+                .retain_mut(|spectator| spectator.send(&Message::Ping).is_ok());
+        }
+
+        self.send_changes(controller);
+        self.broadcast(controller);
+        self.update_waiting(controller);
+        self.save(controller);
+    }
+
+    // Picks up new connections, and has a joining player try again after the
+    // connection failed or dropped
+    fn connect(&mut self, controller: &mut ChessController) {
+        loop {
+            match self.streams.try_recv() {
+                Ok(Ok(stream)) => match Connection::new(stream) {
+                    Ok(mut connection) => {
+                        let hello = Message::Hello {
+                            version: PROTOCOL_VERSION,
+                            id: self.id,
+                            spectator: self.is_spectator(),
+                            name: self.name.clone(),
+                        };
+                        if connection.send(&hello).is_ok() {
+                            self.pending.push(connection);
+                        }
+                    }
+                    Err(err) => controller.status = Some(err),
+                },
+                Ok(Err(err)) => match self.role {
+                    NetworkRole::Host(..) => controller.show_error(err),
+                    NetworkRole::Join(_) | NetworkRole::Watch(_) => {
+                        controller.status = Some(err);
+                        self.retry_later();
+                    }
+                },
+                Err(_) => break,
+            }
+        }
+
+        let retry = self.retry_at.map_or(false, |at| Instant::now() >= at);
+        let waiting = self.local_color.is_none() && !self.watching;
+        let playing = waiting || controller.game_result.is_none();
+        if retry && playing && self.connection.is_none() && self.pending.is_empty() {
+            self.retry_at = None;
+            self.streams = start_connecting(&self.role);
+        }
+    }
+
+    fn hello(
+        &mut self,
+        controller: &mut ChessController,
+        mut connection: Connection,
+        version: u32,
+        id: u64,
+        spectator: bool,
+        name: String,
+    ) {
+        if version != PROTOCOL_VERSION {
+            let _ = connection.send(&Message::Bye);
+            controller.show_error(format!(
+                "{} uses protocol version {}, this is version {}",
+                name, version, PROTOCOL_VERSION
+            ));
+            return;
+        }
+
+        if spectator {
+            match self.role {
+                NetworkRole::Host(..) => self.add_spectator(controller, connection, name),
+                _ => {
+                    let _ = connection.send(&Message::Bye);
+                }
+            }
+            return;
+        }
+
+        // Somebody else than the player this game is with
+        if self.peer_id.map_or(false, |peer_id| peer_id != id) {
+            let _ = connection.send(&Message::Bye);
+            if let NetworkRole::Join(address) | NetworkRole::Watch(address) = &self.role {
+                controller.show_error(format!("{} is hosting a different game", address));
+            }
+            return;
+        }
+
+        let returning = self.peer_id.is_some();
+        self.peer_id = Some(id);
+        self.peer_name = Some(name);
+        self.connection = Some(connection);
+        self.heard_at = Instant::now();
+
+        if let NetworkRole::Host(_, color) = self.role {
+            let game = Message::Game {
+                color: fen::opposite(color),
+                time_control: controller.options.time_control.clone(),
+                fen: controller.start_fen.to_string(),
+            };
+            self.send(controller, game);
+
+            if returning {
+                let resume = resume_message(controller, usize::MAX);
+                self.send(controller, resume);
+                self.resumed(controller);
+            } else {
+                self.begin(controller, color);
+            }
+        }
+    }
+
+    // Sends the game so far, the rest follows move by move
+    fn add_spectator(
+        &mut self,
+        controller: &mut ChessController,
+        mut connection: Connection,
+        name: String,
+    ) {
+        let mut messages = vec![
+            Message::Game {
+                color: Color::White,
+                time_control: controller.options.time_control.clone(),
+                fen: controller.start_fen.to_string(),
+            },
+            resume_message(controller, self.broadcast_plies),
+        ];
+
+        if let (true, Some(result)) = (self.broadcast_result, controller.game_result) {
+            messages.push(Message::Result(result, self.broadcast_plies));
+        }
+
+        if messages
+            .iter()
+            .all(|message| connection.send(message).is_ok())
+        {
+            controller.status = Some(format!("{} is watching", name));
+            self.spectators.push(connection);
+        }
+    }
+
+    fn receive(&mut self, controller: &mut ChessController, message: Message) {
+        let peer = self.peer();
+
+        match message {
+            Message::Hello { .. } => {}
+            Message::Game {
+                color,
+                time_control,
+                fen,
+            } => {
+                if let NetworkRole::Host(..) = self.role {
+                    return;
+                }
+
+                if self.is_spectator() {
+                    if !self.watching {
+                        controller.options.time_control = time_control;
+                        self.watching = controller.set_position(&fen);
+                        controller.status = Some(format!("Watching the game of {}", peer));
+                    }
+                    return;
+                }
+
+                match self.local_color {
+                    None => {
+                        controller.options.time_control = time_control;
+                        if controller.set_position(&fen) {
+                            self.begin(controller, color);
+                        }
+                    }
+                    Some(local_color) if local_color != color => {
+                        controller.show_error(format!("{} changed colors", peer));
+                    }
+                    Some(_) => {}
+                }
+            }
+            Message::Resume { clock, moves } => {
+                if let NetworkRole::Host(..) = self.role {
+                    return;
+                }
+
+                // The host has the last word on what was played, moves that
+                // got lost on the way are made again
+                let known = controller
+                    .history
+                    .iter()
+                    .chain(controller.redo_stack.iter().rev())
+                    .map(|entry| uci::move_to_uci(&entry.mv))
+                    .collect::<Vec<String>>();
+
+                if known != moves {
+                    if let Err(err) = controller.replay_uci_moves(&moves) {
+                        controller.show_error(format!("Could not resume the game: {}", err));
+                        return;
+                    }
+                }
+
+                if let (Some((white, black)), Some(clock)) = (clock, controller.live_clock_mut()) {
+                    clock.remaining = [white as f64 / 1000.0, black as f64 / 1000.0];
+                    clock.running = !moves.is_empty() && controller.game_result.is_none();
+                }
+
+                self.resumed(controller);
+            }
+            // Spectators follow the host's board, players have the move
+            // checked against the legal moves by the RemotePlayer
+            Message::Move(mv) if self.is_spectator() => {
+                if let Err(err) = controller.append_uci_move(&mv) {
+                    controller.show_error(format!("Could not follow the game: {}", err));
+                }
+            }
+            Message::Move(mv) => {
+                // The clocks that come with the move replace any older ones
+                self.clock = None;
+                if let Some(moves) = &self.moves {
+                    let _ = moves.send(mv);
+                }
+            }
+            Message::Result(result, _) if self.is_spectator() => {
+                if controller.game_result.is_none() {
+                    controller.go_to_end();
+                    controller.end_game(result);
+                }
+            }
+            // Played once the moves before it are
+            Message::Result(result, plies) => self.remote_result = Some((result, plies)),
+            Message::Resign | Message::DrawOffer | Message::DrawAccept | Message::DrawDecline
+                if self.is_spectator() => {}
+            Message::Resign => {
+                if let (Some(color), None) = (self.remote_color(), controller.game_result) {
+                    self.result_sent = true;
+                    controller.resign(color);
+                }
+            }
+            Message::DrawOffer => {
+                if let (Some(color), None) = (self.remote_color(), controller.game_result) {
+                    controller.receive_draw_offer(color);
+                    self.draw_offer = controller.draw_offer;
+                }
+            }
+            Message::DrawAccept => {
+                if controller.draw_offer.is_some() && controller.draw_offer == self.local_color {
+                    controller.status = Some(format!("{} accepts the draw", peer));
+                    self.result_sent = true;
+                    controller.end_game(GameResult::draw(Termination::Agreement));
+                }
+            }
+            Message::DrawDecline => {
+                if controller.draw_offer.is_some() && controller.draw_offer == self.local_color {
+                    controller.draw_offer = None;
+                    self.draw_offer = None;
+                    controller.status = Some(format!("{} declines the draw", peer));
+                }
+            }
+            Message::Clock(white, black) if self.is_spectator() => {
+                if let Some(clock) = controller.live_clock_mut() {
+                    clock.remaining = [white as f64 / 1000.0, black as f64 / 1000.0];
+                }
+            }
+            Message::Clock(white, black) => {
+                self.clock = Some([white as f64 / 1000.0, black as f64 / 1000.0]);
+            }
+            Message::Chat(text) => controller.chat.push(Some(peer), text),
+            Message::Ping => {}
+            // Leaving on purpose is not waited for
+            Message::Bye => {
+                self.connection = None;
+                self.retry_at = None;
+                controller.status = Some(format!("{} left the game", peer));
+
+                if let (Some(color), None) = (self.local_color, controller.game_result) {
+                    self.result_sent = true;
+                    controller.end_game(GameResult::win(color, Termination::Abandonment));
+                }
+            }
+        }
+    }
+
+    // A new channel for the moves from the other side, so that moves sent
+    // before a dropped connection are not played twice
+    fn connect_players(&mut self, controller: &mut ChessController, local_color: Color) {
+        let (sender, receiver) = channel();
+
+        controller.players[color_index(local_color)] = Box::new(HumanPlayer);
+        controller.players[1 - color_index(local_color)] =
+            Box::new(RemotePlayer::new(self.peer(), receiver));
+        self.moves = Some(sender);
+    }
+
+    // Both sides are ready, the local player gets the board for their color
+    fn begin(&mut self, controller: &mut ChessController, local_color: Color) {
+        self.connect_players(controller, local_color);
+
+        controller.flipped = local_color == Color::Black;
+        let color = fen::color_name(local_color);
+        controller.status = Some(format!("Playing {} against {}", color, self.peer()));
+
+        self.local_color = Some(local_color);
+        self.plies = 0;
+        self.draw_offer = None;
+        self.result_sent = false;
+        self.remote_result = None;
+    }
+
+    // The other side is back and both sides have the same moves
+    fn resumed(&mut self, controller: &mut ChessController) {
+        let local_color = match self.local_color {
+            Some(color) => color,
+            None => return,
+        };
+        self.connect_players(controller, local_color);
+
+        self.plies = controller.history.len() + controller.redo_stack.len();
+        self.lost_at = None;
+        self.clock = None;
+        self.draw_offer = None;
+        controller.draw_offer = None;
+        // The other side may have missed how the game ended
+        self.result_sent = false;
+
+        if let Some(clock) = controller.live_clock_mut() {
+            clock.running = self.plies > 0;
+        }
+
+        controller.status = Some(format!("Resumed the game against {}", self.peer()));
+    }
+
+    // Covers the board while there is nobody to play against, and gives the
+    // game to the player that stayed when the other one does not come back
+    fn update_waiting(&mut self, controller: &mut ChessController) {
+        if self.is_spectator() {
+            match self.watching {
+                true => controller.hide_waiting(),
+                false => controller.show_waiting(self.waiting_message()),
+            }
+            return;
+        }
+
+        let local_color = match self.local_color {
+            Some(color) => color,
+            None => {
+                controller.show_waiting(self.waiting_message());
+                return;
+            }
+        };
+
+        let lost_at = match (self.lost_at, controller.game_result) {
+            (Some(lost_at), None) if self.connection.is_none() => lost_at,
+            _ => {
+                self.lost_at = None;
+                controller.hide_waiting();
+                return;
+            }
+        };
+
+        // Nobody loses time while the connection is down
+        if let Some(clock) = controller.live_clock_mut() {
+            clock.running = false;
+        }
+
+        let left = self.abandon_after.saturating_sub(lost_at.elapsed());
+        if left.is_zero() {
+            self.retry_at = None;
+            controller.hide_waiting();
+            controller.status = Some(format!("{} did not come back", self.peer()));
+            controller.end_game(GameResult::win(local_color, Termination::Abandonment));
+            return;
+        }
+
+        controller.show_waiting(format!(
+            "Lost the connection to {}, the game is abandoned in {}s",
+            self.peer(),
+            left.as_secs() + 1
+        ));
+    }
+
+    // Sends what changed on this side since the last update
+    fn send_changes(&mut self, controller: &mut ChessController) {
+        let local_color = match self.local_color {
+            Some(color) => color,
+            None => return,
+        };
+
+        let moves = controller
+            .history
+            .iter()
+            .chain(controller.redo_stack.iter().rev())
+            .map(|entry| entry.mv.clone())
+            .collect::<Vec<Move>>();
+
+        while self.plies < moves.len() {
+            let mover = match self.plies % 2 == 0 {
+                true => controller.start_fen.turn,
+                false => fen::opposite(controller.start_fen.turn),
+            };
+
+            if mover == local_color {
+                let mv = uci::move_to_uci(&moves[self.plies]);
+                self.send(controller, Message::Move(mv));
+
+                if let Some(clock) = controller.live_clock() {
+                    let [white, black] = clock.remaining.map(|time| (time * 1000.0) as u64);
+                    self.send(controller, Message::Clock(white, black));
+                }
+            } else if let Some(remaining) = self.clock.take() {
+                if let Some(clock) = controller.live_clock_mut() {
+                    clock.remaining = remaining;
+                }
+            }
+
+            self.plies += 1;
+        }
+
+        if controller.draw_offer != self.draw_offer {
+            let remote_color = fen::opposite(local_color);
+            let agreed = controller
+                .game_result
+                .map_or(false, |result| result.termination == Termination::Agreement);
+
+            match (self.draw_offer, controller.draw_offer) {
+                (_, Some(color)) if color == local_color => {
+                    self.send(controller, Message::DrawOffer);
+                }
+                (Some(color), None) if color == remote_color && agreed => {
+                    self.result_sent = true;
+                    self.send(controller, Message::DrawAccept);
+                }
+                (Some(color), None) if color == remote_color => {
+                    self.send(controller, Message::DrawDecline);
+                }
+                _ => {}
+            }
+
+            self.draw_offer = controller.draw_offer;
+        }
+
+        // Messages written while the other side was away wait for them
+        if self.connection.is_some() {
+            for text in std::mem::take(&mut controller.chat.outgoing) {
+                self.send(controller, Message::Chat(text));
+            }
+        }
+
+        if let Some((result, plies)) = self.remote_result {
+            if self.plies >= plies {
+                self.remote_result = None;
+                self.result_sent = true;
+                if controller.game_result.is_none() {
+                    controller.end_game(result);
+                }
+            }
+        }
+
+        // Every way the game can end here is passed on, so that both sides
+        // agree on the result
+        if let (Some(result), false) = (controller.game_result, self.result_sent) {
+            self.result_sent = true;
+
+            let resigned = result.termination == Termination::Resignation;
+            let message = match resigned && result.winner != Some(local_color) {
+                true => Message::Resign,
+                false => Message::Result(result, self.plies),
+            };
+            self.send(controller, message);
+        }
+    }
+
+    // Passes the new moves and the result on to the spectators
+    fn broadcast(&mut self, controller: &ChessController) {
+        if self.local_color.is_none() {
+            return;
+        }
+
+        let mut messages = controller
+            .history
+            .iter()
+            .chain(controller.redo_stack.iter().rev())
+            .skip(self.broadcast_plies)
+            .map(|entry| Message::Move(uci::move_to_uci(&entry.mv)))
+            .collect::<Vec<Message>>();
+        self.broadcast_plies += messages.len();
+
+        if let (false, Some(clock)) = (messages.is_empty(), controller.live_clock()) {
+            let [white, black] = clock.remaining.map(|time| (time * 1000.0) as u64);
+            messages.push(Message::Clock(white, black));
+        }
+
+        if let (false, Some(result)) = (self.broadcast_result, controller.game_result) {
+            self.broadcast_result = true;
+            messages.push(Message::Result(result, self.broadcast_plies));
+        }
+
+        if !messages.is_empty() {
+            self.spectators.retain_mut(|spectator| {
+                messages
+                    .iter()
+                    .all(|message| spectator.send(message).is_ok())
+            });
+        }
+    }
+}
+
+// The host's side of the game for a player that joins or comes back, up to
+// `plies` moves
+fn resume_message(controller: &ChessController, plies: usize) -> Message {
+    let moves = controller
+        .history
+        .iter()
+        .chain(controller.redo_stack.iter().rev())
+        .take(plies)
+        .map(|entry| uci::move_to_uci(&entry.mv))
+        .collect();
+
+    let clock = controller.live_clock().map(|clock| {
+        let [white, black] = clock.remaining.map(|time| (time * 1000.0) as u64);
+        (white, black)
+    });
+
+    Message::Resume { clock, moves }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::Options;
+
+    // Writes the message and reads it back, the text must stay the same
+    fn round_trip(message: Message) -> Message {
+        let line = message.to_string();
+        assert!(!line.contains('\n'), "{}", line);
+
+        let parsed = Message::parse(&line).unwrap();
+        assert_eq!(parsed.to_string(), line);
+        parsed
+    }
+
+    #[test]
+    fn hello_round_trips() {
+        for spectator in [false, true] {
+            let hello = round_trip(Message::Hello {
+                version: PROTOCOL_VERSION,
+                id: u64::MAX,
+                spectator,
+                name: "Ada Lovelace".to_string(),
+            });
+
+            match hello {
+                Message::Hello {
+                    version,
+                    id,
+                    spectator: parsed,
+                    name,
+                } => {
+                    assert_eq!(version, PROTOCOL_VERSION);
+                    assert_eq!(id, u64::MAX);
+                    assert_eq!(parsed, spectator);
+                    assert_eq!(name, "Ada Lovelace");
+                }
+                _ => panic!("not a hello"),
+            }
+        }
+    }
+
+    #[test]
+    fn game_round_trips() {
+        for time_control in [None, Some("40/5400+30:1800+30"), Some("180d2")] {
+            let game = round_trip(Message::Game {
+                color: Color::Black,
+                time_control: time_control.map(|text| TimeControl::parse(text).unwrap()),
+                fen: fen::START_FEN.to_string(),
+            });
+
+            match game {
+                Message::Game {
+                    color,
+                    time_control: parsed,
+                    fen,
+                } => {
+                    assert!(color == Color::Black);
+                    assert_eq!(parsed.map(|tc| tc.to_string()).as_deref(), time_control);
+                    assert_eq!(fen, fen::START_FEN);
+                }
+                _ => panic!("not a game"),
+            }
+        }
+    }
+
+    #[test]
+    fn resume_round_trips() {
+        let moves = vec!["e2e4".to_string(), "e7e5".to_string(), "g1f3".to_string()];
+
+        match round_trip(Message::Resume {
+            clock: Some((299_500, 300_000)),
+            moves: moves.clone(),
+        }) {
+            Message::Resume {
+                clock,
+                moves: parsed,
+            } => {
+                assert_eq!(clock, Some((299_500, 300_000)));
+                assert_eq!(parsed, moves);
+            }
+            _ => panic!("not a resume"),
+        }
+
+        match round_trip(Message::Resume {
+            clock: None,
+            moves: Vec::new(),
+        }) {
+            Message::Resume { clock, moves } => {
+                assert_eq!(clock, None);
+                assert!(moves.is_empty());
+            }
+            _ => panic!("not a resume"),
+        }
+    }
+
+    #[test]
+    fn moves_and_clocks_round_trip() {
+        match round_trip(Message::Move("e7e8q".to_string())) {
+            Message::Move(mv) => assert_eq!(mv, "e7e8q"),
+            _ => panic!("not a move"),
+        }
+
+        match round_trip(Message::Clock(0, 5_400_000)) {
+            Message::Clock(white, black) => assert_eq!((white, black), (0, 5_400_000)),
+            _ => panic!("not a clock"),
+        }
+    }
+
+    #[test]
+    fn results_round_trip() {
+        for termination in Termination::ALL {
+            for result in [
+                GameResult::win(Color::White, termination),
+                GameResult::win(Color::Black, termination),
+                GameResult::draw(termination),
+            ] {
+                match round_trip(Message::Result(result, 41)) {
+                    Message::Result(parsed, plies) => {
+                        assert!(parsed == result);
+                        assert_eq!(plies, 41);
+                    }
+                    _ => panic!("not a result"),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn chat_keeps_separators_and_line_breaks() {
+        for text in [
+            "",
+            "good game",
+            "MOVE e2e4",
+            "two\nlines",
+            "a \\n that is not a line break",
+            "ends with \\",
+        ] {
+            match round_trip(Message::Chat(text.to_string())) {
+                Message::Chat(parsed) => assert_eq!(parsed, text),
+                _ => panic!("not a chat message"),
+            }
+        }
+    }
+
+    #[test]
+    fn messages_without_fields_round_trip() {
+        let messages = [
+            Message::Resign,
+            Message::DrawOffer,
+            Message::DrawAccept,
+            Message::DrawDecline,
+            Message::Ping,
+            Message::Bye,
+        ];
+
+        for message in messages {
+            let line = message.to_string();
+            assert_eq!(round_trip(message).to_string(), line);
+        }
+    }
+
+    #[test]
+    fn invalid_messages_are_rejected() {
+        for line in [
+            "",
+            "HELLO",
+            "HELLO 1 2 referee Bob",
+            "HELLO 4294967297 2 player Bob",
+            "GAME red - 8/8/8/8/8/8/8/8 w - -",
+            "MOVE",
+            "DRAW MAYBE",
+            "CLOCK 10",
+            "RESULT 1-0 checkmate",
+            "RESULT 2-0 checkmate 10",
+            "RESUME 10",
+            "SHOUT hello",
+        ] {
+            assert!(Message::parse(line).is_err(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn other_protocol_versions_are_turned_away() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+
+        let mut controller = ChessController::new(Options::new()).unwrap();
+        let role = NetworkRole::Host(0, Color::White);
+        let mut game =
+            NetworkGame::start(role, "Host".to_string(), Duration::ZERO, &mut controller);
+
+        let connection = Connection::new(server).unwrap();
+        let version = PROTOCOL_VERSION + 1;
+        game.hello(
+            &mut controller,
+            connection,
+            version,
+            7,
+            false,
+            "Bob".to_string(),
+        );
+
+        assert!(game.connection.is_none());
+        assert!(game.peer_id.is_none());
+        let error = controller.error.unwrap_or_default();
+        assert!(error.contains("protocol version"), "{}", error);
+
+        let mut line = String::new();
+        BufReader::new(client).read_line(&mut line).unwrap();
+        assert_eq!(line.trim_end(), "BYE");
+    }
+}
@@ -0,0 +1,344 @@
+use std::path::PathBuf;
+
+use ChessAPI::piece::*;
+
+use crate::ai;
+use crate::backend::BackendKind;
+use crate::clock::color_index;
+use crate::clock::TimeControl;
+use crate::network;
+use crate::network::NetworkRole;
+use crate::uci::EngineSettings;
+
+#[derive(Clone)]
+pub struct Options {
+    pub save_pgn: Option<PathBuf>,
+    pub load_pgn: Option<PathBuf>,
+    pub fen: Option<String>,
+    pub time_control: Option<TimeControl>,
+    pub flipped: bool,
+    pub auto_flip: bool,
+    // Who plays each side, indexed by `clock::color_index`
+    pub players: [PlayerKind; 2],
+    // Number of games to play between the two players
+    pub match_games: Option<u32>,
+    pub openings: Option<PathBuf>,
+    // Runs without a window when set
+    pub headless: Option<HeadlessTask>,
+    // The library that generates and checks moves
+    pub backend: BackendKind,
+    // Seed for random games, taken from the time when not given
+    pub seed: Option<u64>,
+    // Plays against another instance over the network
+    pub network: Option<NetworkRole>,
+    // The name shown to the other side of a network game
+    pub name: Option<String>,
+    // Seconds to wait for a network opponent who lost the connection
+    pub abandon_after: Option<u64>,
+}
+
+#[derive(Clone)]
+pub enum HeadlessTask {
+    // Counts the leaf nodes of the move tree to a depth
+    Perft(u32),
+    ValidatePgn(PathBuf),
+    // Plays the match given with --match
+    Match(u32),
+    // Plays random games and the --load-pgn games through both backends
+    DiffTest(u32),
+}
+
+#[derive(Clone)]
+pub enum PlayerKind {
+    Human,
+    // The built-in AI at a difficulty level
+    Ai(u32),
+    Engine(EngineSettings),
+    // Replays the moves of the first game in a PGN file
+    Replay(PathBuf),
+    // The other side of a network game
+    Remote,
+}
+
+impl PlayerKind {
+    // Parses "human", "ai[:level]", "engine:<path>" or "replay:<path>"
+    pub fn parse(text: &str) -> Result<PlayerKind, String> {
+        let (kind, value) = match text.split_once(':') {
+            Some((kind, value)) => (kind, Some(value)),
+            None => (text, None),
+        };
+
+        match (kind, value) {
+            ("human", None) => Ok(PlayerKind::Human),
+            ("ai", None) => Ok(PlayerKind::Ai(3)),
+            ("ai", Some(level)) => Ok(PlayerKind::Ai(ai_level(level.to_string())?)),
+            ("engine", Some(path)) => Ok(PlayerKind::Engine(EngineSettings::new(path.into()))),
+            ("replay", Some(path)) => Ok(PlayerKind::Replay(PathBuf::from(path))),
+            _ => Err(format!("Unknown player {}", text)),
+        }
+    }
+}
+
+pub const USAGE: &str = "Usage: fritiofr-chess-gui [options]
+
+Options:
+    --save-pgn <path>    Save the game to <path> with Ctrl+S and when the window is closed
+    --load-pgn <path>    Open the games in <path> for replay, Ctrl+O reopens the file
+    --fen <fen>          Start from the position <fen> instead of the standard position
+    --fen-file <path>    Start from the FEN stored in <path>
+    --time <control>     Play with clocks, in seconds like the PGN TimeControl tag.
+                         \"300+2\" adds a 2s increment, \"300d2\" uses a 2s Bronstein
+                         delay and stages are separated by ':', e.g. \"40/5400+30:1800+30\"
+    --black              Show the board from Black's side, F flips it while playing
+    --auto-flip          Turn the board towards the side to move after every move,
+                         A toggles this while playing
+    --engine <path>      Play against a UCI engine such as Stockfish
+    --ai <level>         Play against the built-in AI, from 1 (weakest) to 5
+    --play-as <color>    The color to play against the engine or when hosting a
+                         network game, white or black
+    --white-player <p>   Who plays White: human, ai[:level], engine:<path> or
+                         replay:<pgn>, the replay plays the moves of a recorded game
+    --black-player <p>   Who plays Black, like --white-player
+    --match <n>          Play <n> games between the White and Black players, swapping
+                         colors after every game, and save them to --save-pgn
+                         (default match.pgn)
+    --openings <path>    Start the match games from the lines in a PGN file, or from
+                         a file with one FEN or EPD position per line
+    --depth <n>          Let the engine search to depth <n>
+    --movetime <ms>      Let the engine think <ms> milliseconds per move (default 1000),
+                         with --time the engine divides its clock time itself
+    --skill <n>          Set the engine's Skill Level option
+    --backend <name>     The move generator to use, chessapi (default) or fritiofr
+
+Network games:
+    --host               Wait for another player to join over the network, playing
+                         the color given with --play-as
+    --port <n>           The port to host on (default 7878)
+    --join <address>     Join a game hosted at <address>, like 192.168.1.2 or
+                         192.168.1.2:7878
+    --watch <address>    Follow a game hosted at <address> without playing, the arrow
+                         keys step through the moves played so far
+    --name <name>        The name shown to the other player
+    --abandon-after <s>  Seconds to wait for an opponent who lost the connection
+                         before they lose the game (default 60). Players keep
+                         the game in network-host-<port>.txt or
+                         network-join-<address>.txt, and starting again with the
+                         same --host or --join resumes it
+
+Without a window:
+    --perft <depth>      Count the positions <depth> moves from --fen, per move
+    --validate-pgn <path>
+                         Check that every game in <path> can be replayed
+    --headless           Play the --match without a window, printing the results
+    --difftest <n>       Play <n> random games and the --load-pgn games with both
+                         backends and report the first position where they disagree
+    --seed <n>           Seed for the random games of --difftest
+    --help               Print this message";
+
+fn number(name: &str, value: String) -> Result<u64, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} expects a number, got {}", name, value))
+}
+
+// A number that has to fit a smaller type, like a search depth
+fn number_in<T: TryFrom<u64>>(name: &str, value: String) -> Result<T, String> {
+    let n = number(name, value)?;
+    T::try_from(n).map_err(|_| format!("{} is too large: {}", name, n))
+}
+
+fn ai_level(value: String) -> Result<u32, String> {
+    match number("The AI level", value)? {
+        level if (1..=ai::MAX_LEVEL as u64).contains(&level) => Ok(level as u32),
+        _ => Err(format!("The AI level goes from 1 to {}", ai::MAX_LEVEL)),
+    }
+}
+
+impl Options {
+    pub fn new() -> Options {
+        Options {
+            save_pgn: None,
+            load_pgn: None,
+            fen: None,
+            time_control: None,
+            flipped: false,
+            auto_flip: false,
+            players: [PlayerKind::Human, PlayerKind::Human],
+            match_games: None,
+            openings: None,
+            headless: None,
+            backend: BackendKind::ChessApi,
+            seed: None,
+            network: None,
+            name: None,
+            abandon_after: None,
+        }
+    }
+
+    pub fn from_args(args: impl Iterator<Item = String>) -> Result<Options, String> {
+        let mut options = Options::new();
+        let mut args = args.skip(1);
+
+        let (mut depth, mut movetime, mut skill) = (None, None, None);
+        // The opponent given with --engine or --ai and the side given with --play-as
+        let (mut opponent, mut play_as) = (None, Color::White);
+        let mut headless_match = false;
+        let (mut host, mut port, mut join) = (false, network::DEFAULT_PORT, None);
+        let mut watch = None;
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for {}", name))
+            };
+
+            match arg.as_str() {
+                "--save-pgn" => options.save_pgn = Some(PathBuf::from(value(&arg)?)),
+                "--load-pgn" => options.load_pgn = Some(PathBuf::from(value(&arg)?)),
+                "--fen" => options.fen = Some(value(&arg)?),
+                "--fen-file" => {
+                    let path = value(&arg)?;
+                    let fen = std::fs::read_to_string(&path)
+                        .map_err(|err| format!("Could not read {}: {}", path, err))?;
+                    options.fen = Some(fen.trim().to_string());
+                }
+                "--time" => options.time_control = Some(TimeControl::parse(&value(&arg)?)?),
+                "--black" => options.flipped = true,
+                "--auto-flip" => options.auto_flip = true,
+                "--engine" => {
+                    if opponent.is_some() {
+                        return Err("--engine and --ai can not be used together".to_string());
+                    }
+                    let settings = EngineSettings::new(PathBuf::from(value(&arg)?));
+                    opponent = Some(PlayerKind::Engine(settings));
+                }
+                "--ai" => {
+                    if opponent.is_some() {
+                        return Err("--engine and --ai can not be used together".to_string());
+                    }
+                    opponent = Some(PlayerKind::Ai(ai_level(value(&arg)?)?));
+                }
+                "--white-player" => options.players[0] = PlayerKind::parse(&value(&arg)?)?,
+                "--black-player" => options.players[1] = PlayerKind::parse(&value(&arg)?)?,
+                "--play-as" => {
+                    play_as = match value(&arg)?.as_str() {
+                        "white" => Color::White,
+                        "black" => Color::Black,
+                        other => return Err(format!("Unknown color {}", other)),
+                    }
+                }
+                "--match" => options.match_games = Some(number_in(&arg, value(&arg)?)?),
+                "--openings" => options.openings = Some(PathBuf::from(value(&arg)?)),
+                "--perft" => {
+                    let depth: u32 = number_in(&arg, value(&arg)?)?;
+                    if depth == 0 {
+                        return Err("--perft needs a depth of at least 1".to_string());
+                    }
+                    options.headless = Some(HeadlessTask::Perft(depth));
+                }
+                "--validate-pgn" => {
+                    options.headless = Some(HeadlessTask::ValidatePgn(PathBuf::from(value(&arg)?)))
+                }
+                "--headless" => headless_match = true,
+                "--difftest" => {
+                    let games = number_in(&arg, value(&arg)?)?;
+                    options.headless = Some(HeadlessTask::DiffTest(games));
+                }
+                "--seed" => options.seed = Some(number(&arg, value(&arg)?)?),
+                "--host" => host = true,
+                "--port" => port = number_in(&arg, value(&arg)?)?,
+                "--join" => join = Some(value(&arg)?),
+                "--watch" => watch = Some(value(&arg)?),
+                "--name" => options.name = Some(value(&arg)?),
+                "--abandon-after" => options.abandon_after = Some(number(&arg, value(&arg)?)?),
+                "--backend" => options.backend = BackendKind::parse(&value(&arg)?)?,
+                "--depth" => depth = Some(number_in(&arg, value(&arg)?)?),
+                "--movetime" => movetime = Some(number(&arg, value(&arg)?)?),
+                "--skill" => skill = Some(number_in(&arg, value(&arg)?)?),
+                "--help" | "-h" => return Err(USAGE.to_string()),
+                _ => return Err(format!("Unknown argument {}\n\n{}", arg, USAGE)),
+            }
+        }
+
+        let with_port = |address: String| match address.contains(':') {
+            true => address,
+            false => format!("{}:{}", address, port),
+        };
+
+        options.network = match (host, join, watch) {
+            (true, None, None) => Some(NetworkRole::Host(port, play_as)),
+            (false, Some(address), None) => Some(NetworkRole::Join(with_port(address))),
+            (false, None, Some(address)) => Some(NetworkRole::Watch(with_port(address))),
+            (false, None, None) => None,
+            _ => return Err("Only one of --host, --join and --watch can be used".to_string()),
+        };
+
+        if options.network.is_some() {
+            if opponent.is_some() || options.match_games.is_some() {
+                return Err("Network games are played between two people".to_string());
+            }
+
+            // Both sides wait for the connection, the colors are set up once
+            // it is made
+            options.players = [PlayerKind::Remote, PlayerKind::Remote];
+            return Ok(options);
+        }
+
+        if let Some(opponent) = opponent {
+            options.players[color_index(play_as)] = PlayerKind::Human;
+            options.players[1 - color_index(play_as)] = opponent;
+        }
+
+        if headless_match {
+            match options.match_games {
+                Some(games) => options.headless = Some(HeadlessTask::Match(games)),
+                None => return Err("--headless runs a match, set one up with --match".to_string()),
+            }
+        }
+
+        let human = |player: &PlayerKind| matches!(player, PlayerKind::Human);
+        if options.match_games.is_some() && options.players.iter().any(human) {
+            return Err(
+                "A match needs two players set with --white-player and --black-player".to_string(),
+            );
+        }
+
+        for player in options.players.iter_mut() {
+            if let PlayerKind::Engine(engine) = player {
+                engine.depth = depth;
+                engine.movetime = movetime;
+                engine.skill = skill;
+            }
+        }
+
+        // Sit on the human's side of the board
+        if !human(&options.players[0]) && human(&options.players[1]) {
+            options.flipped = true;
+        }
+
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        let args = std::iter::once("fritiofr-chess-gui").chain(args.iter().copied());
+        Options::from_args(args.map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn numbers_have_to_fit() {
+        assert!(parse(&["--host", "--port", "65535"]).is_ok());
+        assert!(parse(&["--host", "--port", "65536"]).is_err());
+        assert!(parse(&["--difftest", "4294967296"]).is_err());
+        assert!(parse(&["--perft", "4294967297"]).is_err());
+
+        let engine = ["--engine", "stockfish", "--depth", "4294967296"];
+        let err = parse(&engine).err().unwrap_or_default();
+        assert_eq!(err, "--depth is too large: 4294967296");
+        assert!(parse(&["--engine", "stockfish", "--skill", "-1"]).is_err());
+    }
+}
@@ -0,0 +1,451 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::path::Path;
+use std::str::Chars;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ChessAPI::board::*;
+use ChessAPI::piece::*;
+
+use crate::backend::BackendKind;
+use crate::fen::Fen;
+use crate::san;
+
+pub struct PgnError {
+    // Zero based index of the game in the file
+    pub game: usize,
+    // One based ply the error occurred at, 0 if it is not related to a move
+    pub ply: usize,
+    // The number of the game's first move and whether Black makes it
+    pub first_move: usize,
+    pub black_first: bool,
+    pub token: String,
+    pub message: String,
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.ply == 0 {
+            write!(f, "Game {}: {}", self.game + 1, self.message)
+        } else {
+            let ply = self.ply - 1 + self.black_first as usize;
+            write!(
+                f,
+                "Game {}, ply {} ({}{}): {} \"{}\"",
+                self.game + 1,
+                self.ply,
+                self.first_move + ply / 2,
+                if ply % 2 == 0 { "." } else { "..." },
+                self.message,
+                self.token
+            )
+        }
+    }
+}
+
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<String>,
+    pub result: String,
+}
+
+impl PgnGame {
+    pub fn new(moves: Vec<String>, result: &str) -> PgnGame {
+        PgnGame {
+            // The Seven Tag Roster, in the order the standard requires
+            tags: vec![
+                ("Event".to_string(), "Casual game".to_string()),
+                ("Site".to_string(), "fritiofr-chess-gui".to_string()),
+                ("Date".to_string(), today()),
+                ("Round".to_string(), "-".to_string()),
+                ("White".to_string(), "?".to_string()),
+                ("Black".to_string(), "?".to_string()),
+                ("Result".to_string(), result.to_string()),
+            ],
+            moves,
+            result: result.to_string(),
+        }
+    }
+
+    // A game without tags or moves, filled in while parsing or playing
+    pub fn empty() -> PgnGame {
+        PgnGame {
+            tags: Vec::new(),
+            moves: Vec::new(),
+            result: "*".to_string(),
+        }
+    }
+
+    // A short description used when picking a game from a file
+    pub fn title(&self) -> String {
+        format!(
+            "{} - {} ({})",
+            self.tag("White").unwrap_or("?"),
+            self.tag("Black").unwrap_or("?"),
+            self.result
+        )
+    }
+
+    // Resolves the SAN movetext into legal moves, `index` is only used for errors
+    pub fn legal_moves(&self, index: usize, kind: BackendKind) -> Result<Vec<Move>, PgnError> {
+        let start = match self.tag("FEN") {
+            Some(fen) => Fen::parse(fen)
+                .and_then(|fen| fen.to_board(kind))
+                .map_err(|err| format!("{}", err)),
+            None => kind.new_board(),
+        };
+        let mut board = start.map_err(|message| PgnError {
+            game: index,
+            ply: 0,
+            first_move: 1,
+            black_first: false,
+            token: self.tag("FEN").unwrap_or("").to_string(),
+            message,
+        })?;
+        let mut moves = Vec::new();
+        let (first_move, black_first) = self.first_move();
+
+        for (i, token) in self.moves.iter().enumerate() {
+            let error = |message: String| PgnError {
+                game: index,
+                ply: i + 1,
+                first_move,
+                black_first,
+                token: token.clone(),
+                message,
+            };
+
+            let mv = san::parse_san(&board, token).map_err(|err| error(format!("{}", err)))?;
+            board.make_move(&mv).map_err(error)?;
+            moves.push(mv);
+        }
+
+        Ok(moves)
+    }
+
+    // Games set up from a FEN may start at any move number, and with Black
+    fn first_move(&self) -> (usize, bool) {
+        match self.tag("FEN").and_then(|fen| Fen::parse(fen).ok()) {
+            Some(fen) => (fen.fullmove as usize, fen.turn == Color::Black),
+            None => (1, false),
+        }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn to_pgn(&self) -> String {
+        let mut out = String::new();
+
+        for (name, value) in &self.tags {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            out.push_str(&format!("[{} \"{}\"]\n", name, value));
+        }
+        out.push('\n');
+
+        let (first_move, black_first) = self.first_move();
+
+        let mut tokens = Vec::new();
+        for (i, mv) in self.moves.iter().enumerate() {
+            let ply = i + black_first as usize;
+            if ply % 2 == 0 {
+                tokens.push(format!("{}.", first_move + ply / 2));
+            } else if i == 0 {
+                tokens.push(format!("{}...", first_move));
+            }
+            tokens.push(mv.clone());
+        }
+        tokens.push(self.result.clone());
+
+        // Movetext lines should not be longer than 80 characters
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > 80 {
+                out.push_str(&line);
+                out.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        out.push_str(&line);
+        out.push('\n');
+
+        out
+    }
+}
+
+fn is_result(token: &str) -> bool {
+    matches!(token, "1-0" | "0-1" | "1/2-1/2" | "*")
+}
+
+fn skip_until(chars: &mut Peekable<Chars>, end: char) -> bool {
+    for c in chars.by_ref() {
+        if c == end {
+            return true;
+        }
+    }
+    false
+}
+
+// Games whose movetext does not end with a result keep the one from the
+// Result tag
+fn finish(mut game: PgnGame) -> PgnGame {
+    if let Some(result) = game.tag("Result") {
+        game.result = result.to_string();
+    }
+    game
+}
+
+// Parses every game in a PGN file, the moves are only checked for legality
+// by `PgnGame::legal_moves`
+pub fn parse_pgn(text: &str) -> Result<Vec<PgnGame>, PgnError> {
+    let mut games = Vec::new();
+    let mut game = PgnGame::empty();
+    let mut in_movetext = false;
+    let mut variation_depth = 0;
+    let mut line_start = true;
+
+    let mut chars = text.chars().peekable();
+
+    let error = |games: &Vec<PgnGame>, game: &PgnGame, message: &str| PgnError {
+        game: games.len(),
+        ply: 0,
+        first_move: 1,
+        black_first: false,
+        token: String::new(),
+        message: format!("{} after {} moves", message, game.moves.len()),
+    };
+
+    while let Some(c) = chars.next() {
+        let was_line_start = line_start;
+        line_start = c == '\n';
+
+        match c {
+            // Escaped lines are ignored
+            '%' if was_line_start => {
+                skip_until(&mut chars, '\n');
+                line_start = true;
+            }
+            '[' if variation_depth == 0 => {
+                if in_movetext {
+                    games.push(finish(std::mem::replace(&mut game, PgnGame::empty())));
+                    in_movetext = false;
+                }
+
+                let mut tag = String::new();
+                let mut in_string = false;
+                let mut escaped = false;
+                loop {
+                    match chars.next() {
+                        Some('\\') if in_string && !escaped => escaped = true,
+                        Some('"') if !escaped => in_string = !in_string,
+                        Some(']') if !in_string => break,
+                        Some(c) => {
+                            escaped = false;
+                            tag.push(c);
+                        }
+                        None => return Err(error(&games, &game, "Unterminated tag")),
+                    }
+                }
+
+                let tag = tag.trim();
+                match tag.split_once(char::is_whitespace) {
+                    Some((name, value)) => game.set_tag(name, value.trim()),
+                    None => return Err(error(&games, &game, "Tag without a value")),
+                }
+            }
+            '{' => {
+                if !skip_until(&mut chars, '}') {
+                    return Err(error(&games, &game, "Unterminated comment"));
+                }
+            }
+            ';' => {
+                skip_until(&mut chars, '\n');
+                line_start = true;
+            }
+            '(' => variation_depth += 1,
+            ')' => variation_depth = usize::max(variation_depth, 1) - 1,
+            c if c.is_whitespace() => {}
+            c => {
+                let mut token = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "[]{}();".contains(c) {
+                        break;
+                    }
+                    token.push(c);
+                    chars.next();
+                }
+
+                if variation_depth > 0 || token.starts_with('$') {
+                    continue;
+                }
+
+                if is_result(&token) {
+                    game.result = token;
+                    games.push(std::mem::replace(&mut game, PgnGame::empty()));
+                    in_movetext = false;
+                    continue;
+                }
+
+                // Move numbers can be glued to the move, as in "1.e4" or "12...Nf6"
+                let number = token.len()
+                    - token.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+                let mv = if number > 0 && token[number..].starts_with('.') {
+                    token[number..].trim_start_matches('.')
+                } else {
+                    &token
+                };
+
+                if !mv.is_empty() {
+                    game.moves.push(mv.to_string());
+                    in_movetext = true;
+                }
+            }
+        }
+    }
+
+    if in_movetext || !game.tags.is_empty() {
+        games.push(finish(game));
+    }
+
+    Ok(games)
+}
+
+// Reads every game in the file at `path`, a file without any is an error
+pub fn read_pgn_file(path: &Path) -> Result<Vec<PgnGame>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+
+    match parse_pgn(&text) {
+        Ok(games) if games.is_empty() => {
+            Err(format!("{} does not contain any games", path.display()))
+        }
+        Ok(games) => Ok(games),
+        Err(err) => Err(format!("{}: {}", path.display(), err)),
+    }
+}
+
+// Writes `games` to `path`, replacing what was there
+pub fn write_pgn_file(path: &Path, games: &[PgnGame]) -> Result<(), String> {
+    let text = games
+        .iter()
+        .map(|game| game.to_pgn())
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    std::fs::write(path, text).map_err(|err| format!("Could not save {}: {}", path.display(), err))
+}
+
+// Today's date in the PGN "YYYY.MM.DD" format (UTC)
+pub fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() / 86400)
+        .unwrap_or(0) as i64;
+
+    // Converts days since 1970-01-01 to a civil date
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Vec<PgnGame> {
+        parse_pgn(text).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    #[test]
+    fn comments_nags_and_variations_are_skipped() {
+        let games = parse(
+            "[Event \"Test\"]\n[White \"A \\\"B\\\"\"]\n\n\
+             1. e4 {best by test} e5 $1 2. Nf3 (2. f4 exf4 (2... d5)) 2... Nc6 ; the rest\n\
+             % an escaped line\n\
+             3.Bb5 a6!? 1-0\n",
+        );
+
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].tag("White"), Some("A \"B\""));
+        assert_eq!(games[0].moves, ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6!?"]);
+        assert_eq!(games[0].result, "1-0");
+        assert_eq!(
+            games[0].legal_moves(0, BackendKind::ChessApi).ok().map(|moves| moves.len()),
+            Some(6)
+        );
+    }
+
+    #[test]
+    fn every_game_in_a_file_is_read() {
+        let games = parse(
+            "[Result \"0-1\"]\n\n1. e4 e5\n\n\
+             [Result \"1/2-1/2\"]\n\n1. d4 d5 1/2-1/2\n\n\
+             1. c4 *\n\n\
+             [Result \"1-0\"]\n\n1. Nf3",
+        );
+
+        let results = games
+            .iter()
+            .map(|game| game.result.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(results, ["0-1", "1/2-1/2", "*", "1-0"]);
+        assert_eq!(games[2].moves, ["c4"]);
+        assert_eq!(games[3].moves, ["Nf3"]);
+    }
+
+    #[test]
+    fn games_can_start_from_a_fen() {
+        let fen = "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2";
+        let text = format!("[SetUp \"1\"]\n[FEN \"{}\"]\n\n2... Nc6 3. Bb5 a6 *\n", fen);
+        let games = parse(&text);
+
+        assert_eq!(games[0].moves, ["Nc6", "Bb5", "a6"]);
+        assert_eq!(
+            games[0].legal_moves(0, BackendKind::ChessApi).ok().map(|moves| moves.len()),
+            Some(3)
+        );
+        assert!(games[0].to_pgn().ends_with("\n2... Nc6 3. Bb5 a6 *\n"));
+    }
+
+    #[test]
+    fn errors_name_the_game_and_move() {
+        let fen = "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2";
+        let text = format!("1. e4 *\n\n[FEN \"{}\"]\n\n2... Nc6 3. Bb5 Ke6 *\n", fen);
+        let games = parse(&text);
+
+        let err = games[1].legal_moves(1, BackendKind::ChessApi).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Game 2, ply 3 (3...): illegal move \"Ke6\""
+        );
+
+        let err = parse_pgn("1. e4 *\n\n1. d4 {oops").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Game 2: Unterminated comment after 1 moves"
+        );
+    }
+}
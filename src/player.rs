@@ -0,0 +1,285 @@
+use std::path::Path;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+
+use ChessAPI::board::*;
+
+use crate::ai::Ai;
+use crate::backend::ChessBackend;
+use crate::clock::Clock;
+use crate::fen::Fen;
+use crate::options::PlayerKind;
+use crate::pgn;
+use crate::san;
+use crate::uci;
+use crate::uci::{EngineSettings, UciEngine};
+
+// The game as seen by the side to move
+pub struct GameView<'a> {
+    pub board: &'a dyn ChessBackend,
+    pub start_fen: &'a Fen,
+    // The position on `board`, with the castling rights and en passant square
+    pub fen: &'a Fen,
+    pub moves: &'a [Move],
+    // The hashes of the positions before each move, to find repetitions
+    pub hashes: &'a [u64],
+    pub clock: Option<&'a Clock>,
+}
+
+// Engines take a draw unless they think they are better by more than this
+// many centipawns
+const DRAW_ACCEPT_SCORE: i32 = 25;
+
+// One side of the game. The controller asks the side to move for its move
+// every update until it gets one, players that need time to think do so in
+// the background and answer None in the meantime.
+pub trait Player {
+    fn name(&self) -> String;
+
+    // Human players move by clicking the board instead of through `poll_move`
+    fn is_human(&self) -> bool {
+        false
+    }
+
+    // Only returns legal moves, an error means the player can not go on
+    fn poll_move(&mut self, game: &GameView) -> Result<Option<Move>, String>;
+
+    // Called when it is no longer this player's turn, for example after undoing
+    fn cancel(&mut self) {}
+
+    // Answers a draw offer from the opponent, None when the answer comes from
+    // elsewhere like the draw offer dialog
+    fn accepts_draw(&mut self) -> Option<bool> {
+        None
+    }
+}
+
+pub struct HumanPlayer;
+
+impl Player for HumanPlayer {
+    fn name(&self) -> String {
+        "Human".to_string()
+    }
+
+    fn is_human(&self) -> bool {
+        true
+    }
+
+    fn poll_move(&mut self, _game: &GameView) -> Result<Option<Move>, String> {
+        Ok(None)
+    }
+}
+
+pub struct AiPlayer {
+    ai: Ai,
+    // The ply the current search started at
+    ply: Option<usize>,
+}
+
+impl AiPlayer {
+    pub fn new(level: u32) -> AiPlayer {
+        AiPlayer {
+            ai: Ai::new(level),
+            ply: None,
+        }
+    }
+}
+
+impl Player for AiPlayer {
+    fn name(&self) -> String {
+        format!("Computer (level {})", self.ai.level)
+    }
+
+    fn poll_move(&mut self, game: &GameView) -> Result<Option<Move>, String> {
+        if !self.ai.thinking() || self.ply != Some(game.moves.len()) {
+            self.ply = Some(game.moves.len());
+            self.ai.start(game.board, game.fen, game.hashes);
+            return Ok(None);
+        }
+
+        Ok(self.ai.poll())
+    }
+
+    fn cancel(&mut self) {
+        self.ai.cancel();
+        self.ply = None;
+    }
+
+    fn accepts_draw(&mut self) -> Option<bool> {
+        Some(self.ai.score.map_or(false, |score| score <= DRAW_ACCEPT_SCORE))
+    }
+}
+
+pub struct UciPlayer {
+    engine: UciEngine,
+    ply: Option<usize>,
+}
+
+impl UciPlayer {
+    pub fn start(settings: &EngineSettings) -> Result<UciPlayer, String> {
+        Ok(UciPlayer {
+            engine: UciEngine::start(settings)?,
+            ply: None,
+        })
+    }
+}
+
+impl Player for UciPlayer {
+    fn name(&self) -> String {
+        self.engine.name.clone()
+    }
+
+    fn poll_move(&mut self, game: &GameView) -> Result<Option<Move>, String> {
+        if !self.engine.searching || self.ply != Some(game.moves.len()) {
+            self.engine.cancel();
+            self.ply = Some(game.moves.len());
+            self.engine.go(&game.start_fen.to_string(), game.moves, game.clock)?;
+            return Ok(None);
+        }
+
+        match self.engine.poll()? {
+            Some(best_move) => match uci::parse_uci_move(game.board, &best_move) {
+                Some(mv) => Ok(Some(mv)),
+                None => Err(format!("The engine played an illegal move {}", best_move)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn cancel(&mut self) {
+        self.engine.cancel();
+        self.ply = None;
+    }
+
+    fn accepts_draw(&mut self) -> Option<bool> {
+        Some(self.engine.score.map_or(false, |score| score <= DRAW_ACCEPT_SCORE))
+    }
+}
+
+// A player on the other end of a connection. Moves arrive in long algebraic
+// notation on a channel and are checked before they are played.
+pub struct RemotePlayer {
+    name: String,
+    // None until the connection is made
+    moves: Option<Receiver<String>>,
+}
+
+impl RemotePlayer {
+    pub fn new(name: String, moves: Receiver<String>) -> RemotePlayer {
+        RemotePlayer {
+            name,
+            moves: Some(moves),
+        }
+    }
+
+    pub fn connecting() -> RemotePlayer {
+        RemotePlayer {
+            name: "Opponent".to_string(),
+            moves: None,
+        }
+    }
+}
+
+impl Player for RemotePlayer {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn poll_move(&mut self, game: &GameView) -> Result<Option<Move>, String> {
+        let moves = match &self.moves {
+            Some(moves) => moves,
+            None => return Ok(None),
+        };
+
+        match moves.try_recv() {
+            Ok(text) => match uci::parse_uci_move(game.board, &text) {
+                Some(mv) => Ok(Some(mv)),
+                None => Err(format!("{} sent an illegal move {}", self.name, text)),
+            },
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(format!("{} disconnected", self.name)),
+        }
+    }
+}
+
+// Plays the moves of a recorded game, a little slower than instantly so the
+// moves can be followed
+pub struct ScriptedPlayer {
+    name: String,
+    // SAN moves from the start of the game, for both sides
+    moves: Vec<String>,
+    waiting_since: Option<(usize, Instant)>,
+}
+
+impl ScriptedPlayer {
+    pub fn new(name: String, moves: Vec<String>) -> ScriptedPlayer {
+        ScriptedPlayer {
+            name,
+            moves,
+            waiting_since: None,
+        }
+    }
+
+    // Replays the first game in a PGN file
+    pub fn from_pgn_file(path: &Path) -> Result<ScriptedPlayer, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+        let game = pgn::parse_pgn(&text)
+            .map_err(|err| err.to_string())?
+            .into_iter()
+            .next()
+            .ok_or_else(|| format!("{} does not contain a game", path.display()))?;
+
+        Ok(ScriptedPlayer::new(format!("Replay of {}", game.title()), game.moves))
+    }
+}
+
+impl Player for ScriptedPlayer {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn poll_move(&mut self, game: &GameView) -> Result<Option<Move>, String> {
+        let ply = game.moves.len();
+
+        let since = match self.waiting_since {
+            Some((waiting_ply, since)) if waiting_ply == ply => since,
+            _ => {
+                self.waiting_since = Some((ply, Instant::now()));
+                return Ok(None);
+            }
+        };
+
+        if since.elapsed() < Duration::from_millis(500) {
+            return Ok(None);
+        }
+
+        let text = self
+            .moves
+            .get(ply)
+            .ok_or_else(|| format!("{} has no more moves", self.name))?;
+
+        san::parse_san(game.board, text)
+            .map(Some)
+            .map_err(|err| format!("{} can not play {}: {}", self.name, text, err))
+    }
+
+    fn cancel(&mut self) {
+        self.waiting_since = None;
+    }
+
+    // Recorded games go on as they were played
+    fn accepts_draw(&mut self) -> Option<bool> {
+        Some(false)
+    }
+}
+
+pub fn create_player(kind: &PlayerKind) -> Result<Box<dyn Player>, String> {
+    Ok(match kind {
+        PlayerKind::Human => Box::new(HumanPlayer),
+        PlayerKind::Ai(level) => Box::new(AiPlayer::new(*level)),
+        PlayerKind::Engine(settings) => Box::new(UciPlayer::start(settings)?),
+        PlayerKind::Replay(path) => Box::new(ScriptedPlayer::from_pgn_file(path)?),
+        PlayerKind::Remote => Box::new(RemotePlayer::connecting()),
+    })
+}
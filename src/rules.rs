@@ -0,0 +1,109 @@
+use ChessAPI::board::*;
+use ChessAPI::piece::*;
+
+use crate::backend::ChessBackend;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum MoveKind {
+    Quiet,
+    Capture,
+    // The captured pawn is not on the target square
+    EnPassant,
+    Castle,
+    Promotion { capture: bool },
+}
+
+impl MoveKind {
+    pub fn is_capture(&self) -> bool {
+        matches!(
+            self,
+            MoveKind::Capture | MoveKind::EnPassant | MoveKind::Promotion { capture: true }
+        )
+    }
+}
+
+// Has to be called before the move is made on the board
+pub fn classify(board: &dyn ChessBackend, mv: &Move) -> MoveKind {
+    let squares = board.get_board();
+    let piece = match squares[mv.from.row as usize][mv.from.col as usize] {
+        Some(piece) => piece,
+        None => return MoveKind::Quiet,
+    };
+    let target = squares[mv.to.row as usize][mv.to.col as usize].is_some();
+
+    match piece.piece_type {
+        PieceType::King if (mv.from.col - mv.to.col).abs() == 2 => MoveKind::Castle,
+        PieceType::Pawn if mv.to.row == 0 || mv.to.row == 7 => {
+            MoveKind::Promotion { capture: target }
+        }
+        // A pawn moving diagonally onto an empty square
+        PieceType::Pawn if mv.from.col != mv.to.col && !target => MoveKind::EnPassant,
+        _ if target => MoveKind::Capture,
+        _ => MoveKind::Quiet,
+    }
+}
+
+// The square of the piece `mv` takes, as (col, row)
+pub fn captured_square(board: &dyn ChessBackend, mv: &Move) -> Option<(usize, usize)> {
+    match classify(board, mv) {
+        MoveKind::EnPassant => Some((mv.to.col as usize, mv.from.row as usize)),
+        kind if kind.is_capture() => Some((mv.to.col as usize, mv.to.row as usize)),
+        _ => None,
+    }
+}
+
+// Whether `color` could checkmate the opponent by any series of legal moves,
+// used to decide if running out of time loses or draws
+pub fn can_checkmate(pieces: &[[Option<Piece>; 8]; 8], color: Color) -> bool {
+    let squares = (0..8).flat_map(|row| (0..8).map(move |col| (col, row)));
+
+    let own = squares
+        .clone()
+        .filter_map(|(col, row)| pieces[row][col].map(|piece| (piece, (col + row) % 2)))
+        .filter(|(piece, _)| piece.color == color && piece.piece_type != PieceType::King)
+        .collect::<Vec<(Piece, usize)>>();
+
+    let opponent = squares
+        .filter_map(|(col, row)| pieces[row][col].map(|piece| (piece, (col + row) % 2)))
+        .filter(|(piece, _)| piece.color != color && piece.piece_type != PieceType::King)
+        .collect::<Vec<(Piece, usize)>>();
+
+    if own.iter().any(|(piece, _)| {
+        matches!(
+            piece.piece_type,
+            PieceType::Pawn | PieceType::Rook | PieceType::Queen
+        )
+    }) {
+        return true;
+    }
+
+    match own.len() {
+        0 => false,
+        // A lone minor piece can only mate if the opponent has something to
+        // block its own king with, except bishops of one square color which
+        // can never mate each other
+        1 => {
+            let (piece, square_color) = own[0];
+            if opponent.is_empty() {
+                return false;
+            }
+
+            piece.piece_type != PieceType::Bishop
+                || !opponent.iter().all(|(other, other_square_color)| {
+                    other.piece_type == PieceType::Bishop && *other_square_color == square_color
+                })
+        }
+        _ => {
+            // Any number of bishops on the same square color can not mate
+            // unless the opponent has pieces that are not such bishops
+            let square_color = own[0].1;
+            let same_bishops = |pieces: &Vec<(Piece, usize)>| {
+                pieces.iter().all(|(piece, sc)| {
+                    piece.piece_type == PieceType::Bishop && *sc == square_color
+                })
+            };
+
+            !(same_bishops(&own) && same_bishops(&opponent))
+        }
+    }
+}
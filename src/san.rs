@@ -0,0 +1,353 @@
+use std::fmt;
+
+use ChessAPI::board::*;
+use ChessAPI::piece::*;
+
+use crate::backend::ChessBackend;
+use crate::rules;
+use crate::rules::MoveKind;
+
+pub enum SanError {
+    Invalid,
+    Illegal,
+    Ambiguous,
+    MissingPromotion,
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SanError::Invalid => write!(f, "not a valid move"),
+            SanError::Illegal => write!(f, "illegal move"),
+            SanError::Ambiguous => write!(f, "ambiguous move"),
+            SanError::MissingPromotion => write!(f, "missing promotion piece"),
+        }
+    }
+}
+
+pub fn file_char(col: i8) -> char {
+    (b'a' + col as u8) as char
+}
+
+pub fn rank_char(row: i8) -> char {
+    (b'8' - row as u8) as char
+}
+
+pub fn square_name(pos: Position) -> String {
+    format!("{}{}", file_char(pos.col), rank_char(pos.row))
+}
+
+pub fn piece_char(piece_type: PieceType) -> char {
+    match piece_type {
+        PieceType::Pawn => 'P',
+        PieceType::Knight => 'N',
+        PieceType::Bishop => 'B',
+        PieceType::Rook => 'R',
+        PieceType::Queen => 'Q',
+        PieceType::King => 'K',
+    }
+}
+
+pub fn char_piece(c: char) -> Option<PieceType> {
+    match c.to_ascii_uppercase() {
+        'P' => Some(PieceType::Pawn),
+        'N' => Some(PieceType::Knight),
+        'B' => Some(PieceType::Bishop),
+        'R' => Some(PieceType::Rook),
+        'Q' => Some(PieceType::Queen),
+        'K' => Some(PieceType::King),
+        _ => None,
+    }
+}
+
+fn char_col(c: char) -> Option<i8> {
+    match c {
+        'a'..='h' => Some(c as i8 - 'a' as i8),
+        _ => None,
+    }
+}
+
+fn char_row(c: char) -> Option<i8> {
+    match c {
+        '1'..='8' => Some('8' as i8 - c as i8),
+        _ => None,
+    }
+}
+
+// Converts a legal move into Standard Algebraic Notation, `board` must be the
+// position before the move is played
+pub fn move_to_san(board: &dyn ChessBackend, mv: &Move) -> String {
+    let piece = board.get_board()[mv.from.row as usize][mv.from.col as usize].unwrap();
+
+    let mut san = String::new();
+
+    let kind = rules::classify(board, mv);
+
+    if kind == MoveKind::Castle {
+        san.push_str(if mv.to.col == 6 { "O-O" } else { "O-O-O" });
+    } else {
+        let capture = kind.is_capture();
+
+        if piece.piece_type == PieceType::Pawn {
+            if capture {
+                san.push(file_char(mv.from.col));
+            }
+        } else {
+            san.push(piece_char(piece.piece_type));
+
+            let others = board
+                .generate_legal_moves()
+                .into_iter()
+                .filter(|other| {
+                    (other.to.col, other.to.row) == (mv.to.col, mv.to.row)
+                        && (other.from.col, other.from.row) != (mv.from.col, mv.from.row)
+                        && board.get_board()[other.from.row as usize][other.from.col as usize]
+                            .map(|p| p.piece_type)
+                            == Some(piece.piece_type)
+                })
+                .collect::<Vec<Move>>();
+
+            if !others.is_empty() {
+                if others.iter().all(|other| other.from.col != mv.from.col) {
+                    san.push(file_char(mv.from.col));
+                } else if others.iter().all(|other| other.from.row != mv.from.row) {
+                    san.push(rank_char(mv.from.row));
+                } else {
+                    san.push_str(&square_name(mv.from));
+                }
+            }
+        }
+
+        if capture {
+            san.push('x');
+        }
+
+        san.push_str(&square_name(mv.to));
+
+        if let Some(promotion) = mv.promotion {
+            san.push('=');
+            san.push(piece_char(promotion));
+        }
+    }
+
+    // A move the board does not take gets no check or mate suffix
+    let mut after = board.box_clone();
+    if after.make_move(mv).is_ok() {
+        if after.is_checkmate() {
+            san.push('#');
+        } else if after.is_check() {
+            san.push('+');
+        }
+    }
+
+    san
+}
+
+// Finds the legal move in `board` that a SAN string refers to
+pub fn parse_san(board: &dyn ChessBackend, san: &str) -> Result<Move, SanError> {
+    let san = san.trim_end_matches(|c| matches!(c, '+' | '#' | '!' | '?'));
+    let legal_moves = board.generate_legal_moves();
+    let squares = board.get_board();
+
+    let piece_type_at = |pos: Position| {
+        squares[pos.row as usize][pos.col as usize].map(|piece| piece.piece_type)
+    };
+
+    if matches!(san, "O-O" | "0-0" | "O-O-O" | "0-0-0") {
+        let to_col = if san.len() == 3 { 6 } else { 2 };
+
+        return legal_moves
+            .into_iter()
+            .find(|mv| {
+                piece_type_at(mv.from) == Some(PieceType::King)
+                    && mv.from.col == 4
+                    && mv.to.col == to_col
+            })
+            .ok_or(SanError::Illegal);
+    }
+
+    let mut chars = san.chars().filter(|c| *c != 'x' && *c != '-').collect::<Vec<char>>();
+
+    let promotion = match chars.iter().position(|c| *c == '=') {
+        Some(i) => {
+            let piece = chars.get(i + 1).and_then(|c| char_piece(*c));
+            chars.truncate(i);
+            Some(piece.ok_or(SanError::Invalid)?)
+        }
+        None => match chars.last() {
+            Some(c) if chars.len() > 2 && c.is_ascii_uppercase() => {
+                let piece = char_piece(*c).ok_or(SanError::Invalid)?;
+                chars.pop();
+                Some(piece)
+            }
+            _ => None,
+        },
+    };
+
+    let piece_type = match chars.first() {
+        Some(c) if c.is_ascii_uppercase() => {
+            let piece = char_piece(*c).ok_or(SanError::Invalid)?;
+            chars.remove(0);
+            piece
+        }
+        _ => PieceType::Pawn,
+    };
+
+    if chars.len() < 2 || chars.len() > 4 {
+        return Err(SanError::Invalid);
+    }
+
+    let to_col = char_col(chars[chars.len() - 2]).ok_or(SanError::Invalid)?;
+    let to_row = char_row(chars[chars.len() - 1]).ok_or(SanError::Invalid)?;
+
+    let mut from_col = None;
+    let mut from_row = None;
+    for c in &chars[..chars.len() - 2] {
+        if let Some(col) = char_col(*c) {
+            from_col = Some(col);
+        } else if let Some(row) = char_row(*c) {
+            from_row = Some(row);
+        } else {
+            return Err(SanError::Invalid);
+        }
+    }
+
+    let mut candidates = legal_moves
+        .into_iter()
+        .filter(|mv| {
+            (mv.to.col, mv.to.row) == (to_col, to_row)
+                && piece_type_at(mv.from) == Some(piece_type)
+                && from_col.map_or(true, |col| mv.from.col == col)
+                && from_row.map_or(true, |row| mv.from.row == row)
+                && (mv.promotion.is_none() || mv.promotion == promotion)
+        })
+        .collect::<Vec<Move>>();
+
+    // Promotions may be generated once per piece, in any order, only the
+    // squares matter here
+    candidates.sort_by_key(|mv| (mv.from.col, mv.from.row));
+    candidates.dedup_by_key(|mv| (mv.from.col, mv.from.row));
+
+    if candidates.len() > 1 {
+        return Err(SanError::Ambiguous);
+    }
+
+    let mut mv = candidates.pop().ok_or(SanError::Illegal)?;
+
+    if piece_type == PieceType::Pawn && (to_row == 0 || to_row == 7) {
+        mv.promotion = Some(promotion.ok_or(SanError::MissingPromotion)?);
+    } else if promotion.is_some() {
+        return Err(SanError::Illegal);
+    }
+
+    Ok(mv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{BackendKind, Board};
+    use crate::fen::Fen;
+
+    fn board(kind: BackendKind, fen: &str) -> Board {
+        let fen = Fen::parse(fen).unwrap_or_else(|err| panic!("{}", err));
+        kind.board_from_fen(&fen).unwrap()
+    }
+
+    // Parses `text` and writes the move back in SAN
+    fn rewrite(board: &dyn ChessBackend, text: &str) -> String {
+        let mv = parse_san(board, text).unwrap_or_else(|err| panic!("{}: {}", text, err));
+        move_to_san(board, &mv)
+    }
+
+    fn assert_rewrites(fen: &str, moves: &[(&str, &str)]) {
+        for kind in BackendKind::ALL {
+            let board = board(kind, fen);
+            for (text, san) in moves {
+                assert_eq!(rewrite(board.as_ref(), text), *san, "{}", kind.name());
+            }
+        }
+    }
+
+    #[test]
+    fn pieces_are_told_apart_by_file_rank_or_square() {
+        assert_rewrites(
+            "4k3/8/8/8/8/8/4K3/R6R w - - 0 1",
+            &[("Rad1", "Rad1"), ("Rhd1", "Rhd1"), ("Ra1b1", "Rab1")],
+        );
+        assert_rewrites(
+            "4k3/8/8/R7/8/8/8/R3K3 w - - 0 1",
+            &[("R5a3", "R5a3"), ("R1a3", "R1a3"), ("Rb5", "Rb5")],
+        );
+        assert_rewrites(
+            "8/7k/8/8/8/Q7/8/Q1Q3K1 w - - 0 1",
+            &[("Qa1b2", "Qa1b2"), ("Qa3b2", "Q3b2"), ("Qc1b2", "Qcb2")],
+        );
+    }
+
+    #[test]
+    fn castling() {
+        assert_rewrites(
+            "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1",
+            &[
+                ("O-O", "O-O"),
+                ("O-O-O", "O-O-O"),
+                ("0-0", "O-O"),
+                ("0-0-0", "O-O-O"),
+            ],
+        );
+        assert_rewrites(
+            "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1",
+            &[("O-O", "O-O"), ("O-O-O", "O-O-O")],
+        );
+    }
+
+    #[test]
+    fn promotions() {
+        assert_rewrites(
+            "3r3k/4P3/8/8/8/8/8/K7 w - - 0 1",
+            &[
+                ("exd8=Q", "exd8=Q+"),
+                ("exd8Q", "exd8=Q+"),
+                ("exd8=N", "exd8=N"),
+                ("e8=R", "e8=R+"),
+                ("e8=Q+", "e8=Q+"),
+            ],
+        );
+    }
+
+    #[test]
+    fn checks_and_mates_are_marked() {
+        assert_rewrites(
+            "rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2",
+            &[("Qh4", "Qh4#"), ("Bb4", "Bb4"), ("Qh4#", "Qh4#")],
+        );
+        assert_rewrites(
+            "rnbqkbnr/ppp2ppp/3p4/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 3",
+            &[("Bb5", "Bb5+"), ("Nf3", "Nf3"), ("Qh5", "Qh5")],
+        );
+    }
+
+    #[test]
+    fn wrong_moves_are_rejected() {
+        for kind in BackendKind::ALL {
+            let start = board(kind, crate::fen::START_FEN);
+            for text in ["", "e", "e9", "i4", "Zf3", "Nf3g", "e4=", "e4=X"] {
+                let result = parse_san(start.as_ref(), text);
+                assert!(matches!(result, Err(SanError::Invalid)), "{}", text);
+            }
+            for text in ["e5", "Ke2", "Nd2", "O-O", "O-O-O", "e4=Q"] {
+                let result = parse_san(start.as_ref(), text);
+                assert!(matches!(result, Err(SanError::Illegal)), "{}", text);
+            }
+
+            let rooks = board(kind, "4k3/8/8/8/8/8/4K3/R6R w - - 0 1");
+            let result = parse_san(rooks.as_ref(), "Rd1");
+            assert!(matches!(result, Err(SanError::Ambiguous)));
+
+            let pawn = board(kind, "8/4P3/8/8/8/8/k7/4K3 w - - 0 1");
+            let result = parse_san(pawn.as_ref(), "e8");
+            assert!(matches!(result, Err(SanError::MissingPromotion)));
+        }
+    }
+}
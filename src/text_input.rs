@@ -0,0 +1,153 @@
+use piston::input::*;
+use piston::Event;
+
+use crate::clipboard;
+
+// What an event did to a text input
+#[derive(PartialEq)]
+pub enum InputEvent {
+    // Not a key press or typed text, left for the rest of the window
+    Ignored,
+    // A key the input does not use, still kept from the keyboard shortcuts
+    Handled,
+    // The text changed
+    Edited,
+    // Return was pressed
+    Submit,
+    // Escape was pressed
+    Cancel,
+    Error(String),
+}
+
+// A single line of editable text. Piston sends typed characters as text
+// events and editing keys as button presses, the owner decides which input
+// has the keyboard and what submitting does.
+pub struct TextInput {
+    pub text: String,
+    // Byte offset into `text`, always on a character boundary
+    pub cursor: usize,
+    pub max_chars: usize,
+}
+
+impl TextInput {
+    pub fn new(max_chars: usize) -> TextInput {
+        TextInput {
+            text: String::new(),
+            cursor: 0,
+            max_chars,
+        }
+    }
+
+    pub fn event(&mut self, e: &Event, ctrl_pressed: bool) -> InputEvent {
+        // Holding Ctrl still sends the letter as text on some platforms
+        if let Some(text) = e.text_args() {
+            if ctrl_pressed {
+                return InputEvent::Handled;
+            }
+            return match self.insert(&text) {
+                true => InputEvent::Edited,
+                false => InputEvent::Handled,
+            };
+        }
+
+        let key = match e.press_args() {
+            Some(Button::Keyboard(key)) => key,
+            _ => return InputEvent::Ignored,
+        };
+
+        let edited = match key {
+            Key::Return | Key::NumPadEnter => return InputEvent::Submit,
+            Key::Escape => return InputEvent::Cancel,
+            Key::V if ctrl_pressed => match clipboard::get() {
+                Ok(text) => self.insert(&text.replace('\n', " ")),
+                Err(err) => return InputEvent::Error(err),
+            },
+            Key::Backspace => self.backspace(),
+            Key::Delete => self.delete(),
+            Key::Left => {
+                self.cursor = self
+                    .before_cursor()
+                    .char_indices()
+                    .last()
+                    .map_or(0, |(i, _)| i);
+                false
+            }
+            Key::Right => {
+                let next = self.text[self.cursor..].chars().next();
+                self.cursor += next.map_or(0, |ch| ch.len_utf8());
+                false
+            }
+            Key::Home => {
+                self.cursor = 0;
+                false
+            }
+            Key::End => {
+                self.cursor = self.text.len();
+                false
+            }
+            _ => false,
+        };
+
+        match edited {
+            true => InputEvent::Edited,
+            false => InputEvent::Handled,
+        }
+    }
+
+    pub fn before_cursor(&self) -> &str {
+        &self.text[..self.cursor]
+    }
+
+    // Control characters are left out, returns whether anything was added
+    pub fn insert(&mut self, text: &str) -> bool {
+        let mut inserted = false;
+
+        for ch in text.chars().filter(|ch| !ch.is_control()) {
+            if self.text.chars().count() >= self.max_chars {
+                break;
+            }
+            self.text.insert(self.cursor, ch);
+            self.cursor += ch.len_utf8();
+            inserted = true;
+        }
+
+        inserted
+    }
+
+    pub fn set(&mut self, text: &str) {
+        self.clear();
+        self.insert(text);
+    }
+
+    pub fn clear(&mut self) {
+        self.text.clear();
+        self.cursor = 0;
+    }
+
+    // Empties the input and returns what was in it
+    pub fn take(&mut self) -> String {
+        self.cursor = 0;
+        std::mem::take(&mut self.text)
+    }
+
+    fn backspace(&mut self) -> bool {
+        match self.before_cursor().chars().next_back() {
+            Some(ch) => {
+                self.cursor -= ch.len_utf8();
+                self.text.remove(self.cursor);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn delete(&mut self) -> bool {
+        match self.cursor < self.text.len() {
+            true => {
+                self.text.remove(self.cursor);
+                true
+            }
+            false => false,
+        }
+    }
+}
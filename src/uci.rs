@@ -0,0 +1,372 @@
+use std::io::{BufRead, BufReader, Lines, Write};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ChessAPI::board::*;
+use ChessAPI::piece::*;
+
+use crate::backend::ChessBackend;
+use crate::clock::Clock;
+use crate::san;
+
+#[derive(Clone)]
+pub struct EngineSettings {
+    pub path: PathBuf,
+    pub depth: Option<u32>,
+    // Milliseconds per move
+    pub movetime: Option<u64>,
+    pub skill: Option<u32>,
+}
+
+impl EngineSettings {
+    pub fn new(path: PathBuf) -> EngineSettings {
+        EngineSettings {
+            path,
+            depth: None,
+            movetime: None,
+            skill: None,
+        }
+    }
+}
+
+pub fn move_to_uci(mv: &Move) -> String {
+    let mut uci = format!("{}{}", san::square_name(mv.from), san::square_name(mv.to));
+
+    if let Some(promotion) = mv.promotion {
+        uci.push(san::piece_char(promotion).to_ascii_lowercase());
+    }
+
+    uci
+}
+
+// Finds the legal move a long algebraic move like "e2e4" or "e7e8q" refers
+// to. Castling is written as the king moving two squares, just like the
+// board library generates it.
+pub fn parse_uci_move(board: &dyn ChessBackend, uci: &str) -> Option<Move> {
+    let chars = uci.chars().collect::<Vec<char>>();
+    if chars.len() != 4 && chars.len() != 5 {
+        return None;
+    }
+
+    let square = |file: char, rank: char| -> Option<(i8, i8)> {
+        if !('a'..='h').contains(&file) || !('1'..='8').contains(&rank) {
+            return None;
+        }
+        Some((file as i8 - 'a' as i8, '8' as i8 - rank as i8))
+    };
+
+    let from = square(chars[0], chars[1])?;
+    let to = square(chars[2], chars[3])?;
+    let promotion = match chars.get(4) {
+        Some(c) => Some(san::char_piece(*c)?),
+        None => None,
+    };
+
+    let mut mv = board.generate_legal_moves().into_iter().find(|mv| {
+        (mv.from.col, mv.from.row) == from
+            && (mv.to.col, mv.to.row) == to
+            && (mv.promotion.is_none() || mv.promotion == promotion)
+    })?;
+
+    let pawn = board.get_board()[mv.from.row as usize][mv.from.col as usize]
+        .map(|piece| piece.piece_type)
+        == Some(PieceType::Pawn);
+
+    if pawn && (mv.to.row == 0 || mv.to.row == 7) {
+        mv.promotion = Some(promotion?);
+    }
+
+    Some(mv)
+}
+
+// How long an engine may take to answer the handshake
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+// What the thread reading the engine's output passes on
+enum Output {
+    // The handshake is done, with the engine's name and its input handed back
+    Ready(String, ChildStdin),
+    Line(String),
+    Failed(String),
+}
+
+// A UCI engine running as a subprocess. Its output is read on a separate
+// thread so that neither starting nor searching blocks the event loop, `poll`
+// is called every update to pick up the result.
+pub struct UciEngine {
+    child: Child,
+    // None until the handshake is done, commands are held back until then
+    stdin: Option<ChildStdin>,
+    pending: Vec<String>,
+    started_at: Instant,
+    output: Receiver<Output>,
+    settings: EngineSettings,
+    pub name: String,
+    pub searching: bool,
+    // Searches that were stopped, their best moves are ignored
+    cancelled: usize,
+    // Centipawns from the engine's point of view, from the last info line
+    pub score: Option<i32>,
+}
+
+fn write_line(stdin: &mut ChildStdin, name: &str, command: &str) -> Result<(), String> {
+    writeln!(stdin, "{}", command)
+        .and_then(|_| stdin.flush())
+        .map_err(|err| format!("Engine {} stopped responding: {}", name, err))
+}
+
+// Reads lines until `expected`, picking up the engine's name on the way
+fn wait_for(
+    lines: &mut Lines<BufReader<ChildStdout>>,
+    name: &mut String,
+    expected: &str,
+) -> Result<(), String> {
+    for line in lines {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+
+        if let Some(id) = line.strip_prefix("id name ") {
+            *name = id.trim().to_string();
+        }
+        if line.trim() == expected {
+            return Ok(());
+        }
+    }
+
+    Err(format!("Engine {} exited", name))
+}
+
+// Runs on the thread reading the output so a slow engine does not hold up the
+// window, returns the engine's name
+fn handshake(
+    stdin: &mut ChildStdin,
+    lines: &mut Lines<BufReader<ChildStdout>>,
+    settings: &EngineSettings,
+) -> Result<String, String> {
+    let mut name = settings.path.display().to_string();
+
+    write_line(stdin, &name, "uci")?;
+    wait_for(lines, &mut name, "uciok")?;
+
+    if let Some(skill) = settings.skill {
+        let command = format!("setoption name Skill Level value {}", skill);
+        write_line(stdin, &name, &command)?;
+    }
+
+    write_line(stdin, &name, "isready")?;
+    wait_for(lines, &mut name, "readyok")?;
+    write_line(stdin, &name, "ucinewgame")?;
+
+    Ok(name)
+}
+
+// The go command for the engine's settings. With clocks the engine is given
+// the remaining times, a delay is passed on as an increment.
+fn go_command(settings: &EngineSettings, clock: Option<&Clock>) -> String {
+    let ms = |seconds: f64| (seconds * 1000.0).round() as u64;
+
+    match (settings.depth, clock, settings.movetime) {
+        (Some(depth), _, _) => format!("go depth {}", depth),
+        (None, Some(clock), _) => format!(
+            "go wtime {} btime {} winc {} binc {}",
+            ms(clock.remaining(Color::White)),
+            ms(clock.remaining(Color::Black)),
+            ms(clock.increment(Color::White)),
+            ms(clock.increment(Color::Black))
+        ),
+        (None, None, Some(movetime)) => format!("go movetime {}", movetime),
+        (None, None, None) => "go movetime 1000".to_string(),
+    }
+}
+
+impl UciEngine {
+    pub fn start(settings: &EngineSettings) -> Result<UciEngine, String> {
+        let mut child = Command::new(&settings.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| format!("Could not start {}: {}", settings.path.display(), err))?;
+
+        let mut stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+
+        let (sender, output) = channel();
+        let thread_settings = settings.clone();
+        thread::spawn(move || {
+            let mut lines = BufReader::new(stdout).lines();
+
+            let ready = match handshake(&mut stdin, &mut lines, &thread_settings) {
+                Ok(name) => Output::Ready(name, stdin),
+                Err(err) => Output::Failed(err),
+            };
+            let failed = matches!(ready, Output::Failed(_));
+            if sender.send(ready).is_err() || failed {
+                return;
+            }
+
+            for line in lines {
+                match line {
+                    Ok(line) => {
+                        if sender.send(Output::Line(line)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+        });
+
+        Ok(UciEngine {
+            child,
+            stdin: None,
+            pending: Vec::new(),
+            started_at: Instant::now(),
+            output,
+            settings: settings.clone(),
+            name: settings.path.display().to_string(),
+            searching: false,
+            cancelled: 0,
+            score: None,
+        })
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), String> {
+        match &mut self.stdin {
+            Some(stdin) => write_line(stdin, &self.name, command),
+            None => {
+                self.pending.push(command.to_string());
+                Ok(())
+            }
+        }
+    }
+
+    // Starts searching the position reached by playing `moves` from `fen`,
+    // `clock` is the game's clock if it has one
+    pub fn go(&mut self, fen: &str, moves: &[Move], clock: Option<&Clock>) -> Result<(), String> {
+        let mut position = format!("position fen {}", fen);
+        if !moves.is_empty() {
+            position.push_str(" moves");
+            for mv in moves {
+                position.push(' ');
+                position.push_str(&move_to_uci(mv));
+            }
+        }
+
+        self.send(&position)?;
+        self.send(&go_command(&self.settings, clock))?;
+        self.searching = true;
+        self.score = None;
+
+        Ok(())
+    }
+
+    pub fn cancel(&mut self) {
+        if self.searching {
+            let _ = self.send("stop");
+            self.searching = false;
+            self.cancelled += 1;
+        }
+    }
+
+    // The best move in long algebraic notation once the search is done
+    pub fn poll(&mut self) -> Result<Option<String>, String> {
+        loop {
+            let line = match self.output.try_recv() {
+                Ok(Output::Line(line)) => line,
+                Ok(Output::Ready(name, stdin)) => {
+                    self.name = name;
+                    self.stdin = Some(stdin);
+                    for command in std::mem::take(&mut self.pending) {
+                        self.send(&command)?;
+                    }
+                    continue;
+                }
+                Ok(Output::Failed(err)) => {
+                    self.searching = false;
+                    return Err(err);
+                }
+                Err(TryRecvError::Empty) => {
+                    if self.stdin.is_none() && self.started_at.elapsed() > STARTUP_TIMEOUT {
+                        self.searching = false;
+                        return Err(format!("Engine {} did not answer \"uci\"", self.name));
+                    }
+                    return Ok(None);
+                }
+                Err(TryRecvError::Disconnected) => {
+                    self.searching = false;
+                    return Err(format!("Engine {} exited", self.name));
+                }
+            };
+
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("info") => {
+                    let words = line.split_whitespace().collect::<Vec<&str>>();
+                    if let Some(i) = words.iter().position(|word| *word == "score") {
+                        self.score = match (words.get(i + 1), words.get(i + 2)) {
+                            (Some(&"cp"), Some(cp)) => cp.parse().ok(),
+                            // Mate in n, as a large score that prefers faster mates
+                            (Some(&"mate"), Some(n)) => n
+                                .parse::<i32>()
+                                .ok()
+                                .map(|n| n.signum() * (100_000 - n.abs())),
+                            _ => self.score,
+                        };
+                    }
+                }
+                Some("bestmove") => {
+                    if self.cancelled > 0 {
+                        self.cancelled -= 1;
+                        continue;
+                    }
+
+                    self.searching = false;
+                    return match words.next() {
+                        Some(mv) => Ok(Some(mv.to_string())),
+                        None => Err(format!("Engine {} sent an empty bestmove", self.name)),
+                    };
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::TimeControl;
+
+    #[test]
+    fn engines_are_given_the_clock() {
+        let mut settings = EngineSettings::new(PathBuf::from("engine"));
+        let mut clock = Clock::new(TimeControl::parse("300+2").unwrap());
+        clock.move_made(Color::White);
+        clock.tick(Color::Black, 1.5);
+
+        let with_clock = "go wtime 302000 btime 298500 winc 2000 binc 2000";
+        assert_eq!(go_command(&settings, Some(&clock)), with_clock);
+        assert_eq!(go_command(&settings, None), "go movetime 1000");
+
+        settings.movetime = Some(500);
+        assert_eq!(go_command(&settings, Some(&clock)), with_clock);
+        assert_eq!(go_command(&settings, None), "go movetime 500");
+
+        settings.depth = Some(8);
+        assert_eq!(go_command(&settings, Some(&clock)), "go depth 8");
+    }
+}
@@ -0,0 +1,100 @@
+use ChessAPI::piece::*;
+
+use crate::ai::Random;
+use crate::fen::Fen;
+
+fn piece_index(piece: Piece) -> usize {
+    let color = match piece.color {
+        Color::White => 0,
+        Color::Black => 6,
+    };
+
+    color
+        + match piece.piece_type {
+            PieceType::Pawn => 0,
+            PieceType::Knight => 1,
+            PieceType::Bishop => 2,
+            PieceType::Rook => 3,
+            PieceType::Queen => 4,
+            PieceType::King => 5,
+        }
+}
+
+// Random keys for every feature of a position, a position's hash is the xor
+// of the keys of its features. The keys are the same on every run.
+pub struct Zobrist {
+    pieces: Vec<u64>,
+    side: u64,
+    castling: [u64; 4],
+    en_passant: [u64; 8],
+}
+
+impl Zobrist {
+    pub fn new() -> Zobrist {
+        let mut random = Random(0x9E3779B97F4A7C15);
+
+        Zobrist {
+            pieces: (0..12 * 64).map(|_| random.next()).collect(),
+            side: random.next(),
+            castling: [random.next(), random.next(), random.next(), random.next()],
+            en_passant: [0; 8].map(|_| random.next()),
+        }
+    }
+
+    // The pieces and the side to move
+    fn hash_pieces(&self, pieces: &[[Option<Piece>; 8]; 8], turn: Color) -> u64 {
+        let mut hash = match turn {
+            Color::White => 0,
+            Color::Black => self.side,
+        };
+
+        for (row, rank) in pieces.iter().enumerate() {
+            for (col, square) in rank.iter().enumerate() {
+                if let Some(piece) = square {
+                    hash ^= self.pieces[piece_index(*piece) * 64 + row * 8 + col];
+                }
+            }
+        }
+
+        hash
+    }
+
+    // Positions are the same for the repetition rules when the same pieces are
+    // on the same squares with the same side to move, castling rights and en
+    // passant captures
+    pub fn hash_fen(&self, fen: &Fen) -> u64 {
+        let mut hash = self.hash_pieces(&fen.pieces, fen.turn);
+
+        for (i, right) in [
+            fen.castling.white_king,
+            fen.castling.white_queen,
+            fen.castling.black_king,
+            fen.castling.black_queen,
+        ]
+        .iter()
+        .enumerate()
+        {
+            if *right {
+                hash ^= self.castling[i];
+            }
+        }
+
+        // The en passant square only matters when a pawn stands next to the
+        // pawn that just moved. Pins are not checked.
+        if let Some((col, row)) = fen.en_passant {
+            let pawn_row = if fen.turn == Color::White { row + 1 } else { row - 1 };
+            let capturable = [col.wrapping_sub(1), col + 1].iter().any(|&col| {
+                col < 8
+                    && fen.pieces[pawn_row][col].map_or(false, |piece| {
+                        piece.color == fen.turn && piece.piece_type == PieceType::Pawn
+                    })
+            });
+
+            if capturable {
+                hash ^= self.en_passant[col];
+            }
+        }
+
+        hash
+    }
+}